@@ -0,0 +1,89 @@
+use super::lexer::{Lexer, TextSpan, Token, TokenKind};
+
+// A problem found in the source, pointing at where it occurs.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: TextSpan,
+}
+
+// Lex the input and report only its lexical errors, without parsing it.
+pub fn lint_tokens(input: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for token in Lexer::new(input).tokenize_all() {
+        if let Some(message) = bad_token_message(&token) {
+            diagnostics.push(Diagnostic { message, span: token.span });
+        }
+    }
+    diagnostics
+}
+
+// Describe what is wrong with a `Bad` token, quoting a lone offending character and its code point.
+pub(crate) fn bad_token_message(token: &Token) -> Option<String> {
+    if token.kind != TokenKind::Bad {
+        return None;
+    }
+    let mut chars = token.span.literal.chars();
+    Some(match (chars.next(), chars.next()) {
+        (Some(c), None) => format!("unexpected character '{}' (U+{:04X})", c, c as u32),
+        _ if token.span.literal.starts_with("/*") => "unterminated block comment".to_string(),
+        _ if is_well_formed_integer(&token.span.literal) => {
+            format!("integer literal `{}` is too large", token.span.literal)
+        }
+        _ if is_well_formed_float(&token.span.literal) => {
+            format!("float literal `{}` is too large", token.span.literal)
+        }
+        (Some(c), _) if c.is_ascii_digit() || c == '.' || c == '_' => {
+            format!("malformed number literal `{}`", token.span.literal)
+        }
+        _ => format!("unrecognized word `{}`", token.span.literal),
+    })
+}
+
+// Whether a `Bad` literal is digits with single underscores between them, after any radix prefix,
+// which the lexer only rejects for being too large.
+fn is_well_formed_integer(literal: &str) -> bool {
+    let (digits, radix) = match literal.get(..2) {
+        Some("0x") => (&literal[2..], 16),
+        Some("0o") => (&literal[2..], 8),
+        Some("0b") => (&literal[2..], 2),
+        _ => (literal, 10),
+    };
+    is_digit_groups(digits, radix)
+}
+
+// Whether a `Bad` literal is two runs of decimal digits either side of a point, which the lexer
+// only rejects for being too large.
+fn is_well_formed_float(literal: &str) -> bool {
+    literal.split_once('.').is_some_and(|(whole, fraction)| is_digit_groups(whole, 10) && is_digit_groups(fraction, 10))
+}
+
+// Whether the text is digits in the radix with single underscores between them.
+fn is_digit_groups(digits: &str, radix: u32) -> bool {
+    digits.split('_').all(|group| !group.is_empty() && group.chars().all(|c| c.is_digit(radix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_character_is_quoted_with_its_code_point() {
+        let diagnostics = lint_tokens("2 @ 3");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unexpected character '@' (U+0040)");
+        let error = crate::evaluate("@").unwrap_err();
+        assert_eq!(error.to_string(), "unexpected character '@' (U+0040) at 1:1");
+    }
+
+    #[test]
+    fn lint_tokens_reports_each_lexical_error() {
+        let diagnostics = lint_tokens("1 + $ * 2 /* never closed");
+        let found: Vec<(&str, usize, usize)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.message.as_str(), diagnostic.span.start, diagnostic.span.end))
+            .collect();
+        assert_eq!(found, [("unexpected character '$' (U+0024)", 4, 5), ("unterminated block comment", 10, 25)]);
+        assert!(lint_tokens("1 + ) * 2").is_empty());
+    }
+}
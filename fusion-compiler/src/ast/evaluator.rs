@@ -1,39 +1,549 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
 // Import the ASTVisitor trait to define ASTEvaluator as a visitor for the AST nodes.
+use super::lexer::TextSpan;
+use super::numeric::Numeric;
+use super::value::Value;
 use super::ASTVisitor;
 
-// Define the ASTEvaluator struct to evaluate the AST nodes.
-pub struct ASTEvaluator {
-    pub(crate) last_value: Option<i64>,
+// The errors that can stop evaluation, each pointing at the operator that caused it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum EvalError {
+    Overflow { span: TextSpan },
+    NegativeFactorial { span: TextSpan },
+    FractionalFactorial { span: TextSpan },
+    DivisionByZero { span: TextSpan },
+    NegativeExponent { span: TextSpan },
+    // A reference to a variable that no `let` statement has bound.
+    UndefinedVariable { span: TextSpan },
+    // In strict mode, an integer division that would discard a remainder.
+    InexactDivision { span: TextSpan },
+    // A float literal evaluated by a backend that can't represent it.
+    UnsupportedFloat { span: TextSpan },
+    // In strict mode, an operation combining an integer with a float, with the types of its operands.
+    MixedOperands { span: TextSpan, left: &'static str, right: &'static str },
+    // A float operation with no real result, such as `(-1) ** 0.5`.
+    InvalidOperation { span: TextSpan },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Overflow { span } => {
+                write!(f, "arithmetic overflow at {}", span)
+            }
+            EvalError::NegativeFactorial { span } => {
+                write!(f, "factorial of a negative number at {}", span)
+            }
+            EvalError::FractionalFactorial { span } => {
+                write!(f, "factorial of a number that isn't whole at {}", span)
+            }
+            EvalError::DivisionByZero { span } => {
+                write!(f, "division by zero at {}", span)
+            }
+            EvalError::NegativeExponent { span } => {
+                write!(f, "negative exponent at {}", span)
+            }
+            EvalError::UndefinedVariable { span } => {
+                write!(f, "undefined variable `{}` at {}", span.literal, span)
+            }
+            EvalError::InexactDivision { span } => {
+                write!(f, "division truncates a remainder at {}", span)
+            }
+            EvalError::UnsupportedFloat { span } => {
+                write!(f, "float literal `{}` is not supported at {}", span.literal, span)
+            }
+            EvalError::MixedOperands { span, left, right } => {
+                write!(f, "operation mixes {} and {} operands at {}", left, right, span)
+            }
+            EvalError::InvalidOperation { span } => {
+                write!(f, "operation has no real result at {}", span)
+            }
+        }
+    }
+}
+
+// Define the ASTEvaluator struct to evaluate the AST nodes, generic over the numeric backend.
+pub struct ASTEvaluator<N: Numeric = Value> {
+    pub last_value: Option<N>,
+    // The error that stopped evaluation, if any. While it is set, last_value is None and any
+    // further statements are skipped.
+    pub error: Option<EvalError>,
+    // The number of AST nodes (statements and expressions) visited so far.
+    pub steps: usize,
+    // The values bound by `let` statements, kept across statements so later ones can use them.
+    variables: HashMap<String, N>,
+    // Where `print` statements write their output.
+    writer: Box<dyn Write>,
+    // Whether divisions that leave a remainder are rejected instead of truncated, and operations
+    // mixing an integer with a float instead of converting the integer.
+    strict: bool,
+}
+
+impl<N: Numeric> Default for ASTEvaluator<N> {
+    fn default() -> Self {
+        Self::with_writer(Box::new(io::stdout()))
+    }
 }
 
 impl ASTEvaluator {
-    // Create a new ASTEvaluator instance with the last_value set to None.
+    // Create a new ASTEvaluator instance using the default `Value` backend, with last_value set to None.
     pub fn new() -> Self {
-        Self { last_value: None }
+        Self::default()
+    }
+}
+
+impl<N: Numeric> ASTEvaluator<N> {
+    // Create a new ASTEvaluator instance that sends `print` output to the given writer.
+    pub fn with_writer(writer: Box<dyn Write>) -> Self {
+        Self {
+            last_value: None,
+            error: None,
+            steps: 0,
+            variables: HashMap::new(),
+            writer,
+            strict: false,
+        }
+    }
+
+    // Enable or disable strict mode, where a division that leaves a remainder, or an operation
+    // mixing an integer with a float, is an error.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Evaluate a single expression, returning its value or the error that stopped it.
+    pub fn try_evaluate(&mut self, expression: &super::ASTExpression) -> Result<N, EvalError> {
+        self.visit_expression(expression);
+        match (self.error.take(), self.last_value) {
+            (Some(error), _) => Err(error),
+            (None, Some(value)) => Ok(value),
+            (None, None) => unreachable!("an expression always produces a value or an error"),
+        }
+    }
+
+    // Bind a variable before evaluation starts, as if by `let name = value`.
+    pub fn with_variable(mut self, name: &str, value: N) -> Self {
+        self.variables.insert(name.to_string(), value);
+        self
+    }
+
+    // The variables bound so far, in no particular order.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, N)> + '_ {
+        self.variables.iter().map(|(name, value)| (name.as_str(), *value))
+    }
+
+    // Record an error, which also discards the current value.
+    fn fail(&mut self, error: EvalError) {
+        self.error = Some(error);
+        self.last_value = None;
+    }
+
+    // Apply a binary operator to the values of its operands, failing on division by zero, overflow,
+    // a result that isn't a number and, in strict mode, inexact division or mixed operands.
+    fn apply_binary(&mut self, expr: &super::ASTBinaryExpression, left: N, right: N) {
+        let divides =
+            matches!(expr.operator.kind, super::ASTBinaryOperatorKind::Divide | super::ASTBinaryOperatorKind::Modulo);
+        if divides && right == N::from_i64(0) {
+            self.fail(EvalError::DivisionByZero { span: expr.operator.token.span.clone() });
+            return;
+        }
+        if self.strict && !left.same_kind(right) {
+            let span = expr.operator.token.span.clone();
+            self.fail(EvalError::MixedOperands { span, left: left.type_name(), right: right.type_name() });
+            return;
+        }
+        if self.strict && expr.operator.kind == super::ASTBinaryOperatorKind::Divide && !left.divides_exactly(right) {
+            self.fail(EvalError::InexactDivision { span: expr.operator.token.span.clone() });
+            return;
+        }
+
+        // Evaluate the binary expression based on the operator, failing if the result overflows.
+        let result = match expr.operator.kind {
+            super::ASTBinaryOperatorKind::Plus => left.add(right),
+            super::ASTBinaryOperatorKind::Minus => left.sub(right),
+            super::ASTBinaryOperatorKind::Multiply => left.mul(right),
+            super::ASTBinaryOperatorKind::Divide => left.div(right),
+            super::ASTBinaryOperatorKind::Modulo => left.rem(right),
+            super::ASTBinaryOperatorKind::Power => left.pow(right),
+            // Comparisons produce 1 when they hold and 0 otherwise.
+            super::ASTBinaryOperatorKind::Equals => Some(N::from_bool(left == right)),
+            super::ASTBinaryOperatorKind::NotEquals => Some(N::from_bool(left != right)),
+            super::ASTBinaryOperatorKind::LessThan => Some(N::from_bool(left < right)),
+            super::ASTBinaryOperatorKind::LessThanOrEqual => Some(N::from_bool(left <= right)),
+            super::ASTBinaryOperatorKind::GreaterThan => Some(N::from_bool(left > right)),
+            super::ASTBinaryOperatorKind::GreaterThanOrEqual => Some(N::from_bool(left >= right)),
+        };
+        let span = expr.operator.token.span.clone();
+        match result {
+            Some(value) if value.is_nan() => self.fail(EvalError::InvalidOperation { span }),
+            Some(value) => self.last_value = Some(value),
+            // A power fails on a negative exponent when the backend can't represent the result.
+            None if expr.operator.kind == super::ASTBinaryOperatorKind::Power && right < N::from_i64(0) => {
+                self.fail(EvalError::NegativeExponent { span })
+            }
+            None => self.fail(EvalError::Overflow { span }),
+        }
     }
 }
 
 // Implement the ASTVisitor trait for the ASTEvaluator struct.
-impl ASTVisitor for ASTEvaluator {
+impl<N: Numeric> ASTVisitor for ASTEvaluator<N> {
+    // Count the statement as a step before evaluating it. Nothing runs after an error.
+    fn visit_statement(&mut self, statement: &super::ASTStatement) {
+        if self.error.is_some() {
+            return;
+        }
+        self.steps += 1;
+        self.do_visit_statement(statement);
+    }
+
+    // Count the expression as a step before evaluating it.
+    fn visit_expression(&mut self, expression: &super::ASTExpression) {
+        self.steps += 1;
+        self.do_visit_expression(expression);
+    }
+
+    // Evaluate each argument and write them space-separated, followed by a newline.
+    fn visit_print_statement(&mut self, print_statement: &super::ASTPrintStatement) {
+        let mut values = Vec::new();
+        for argument in &print_statement.arguments {
+            self.visit_expression(argument);
+            let Some(value) = self.last_value else { return };
+            values.push(value.to_string());
+        }
+        writeln!(self.writer, "{}", values.join(" ")).ok();
+        self.last_value = None; // A print statement doesn't produce a value.
+    }
+
+    // Evaluate the initializer and bind its value to the name, replacing any earlier binding.
+    fn visit_let_statement(&mut self, let_statement: &super::ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+        let Some(value) = self.last_value else { return };
+        self.variables.insert(let_statement.name().to_string(), value);
+        self.last_value = None; // A let statement doesn't produce a value.
+    }
+
     // Implement the visit_number method to handle visiting a number node in the AST.
     fn visit_number(&mut self, number: &super::ASTNumberExpression) {
-        self.last_value = Some(number.number) // Set last_value to the value of the number node.
+        self.last_value = Some(N::from_i64(number.number)) // Set last_value to the value of the number node.
+    }
+
+    // Convert a float literal, failing if the backend has no float representation.
+    fn visit_float(&mut self, float: &super::ASTFloatExpression) {
+        match N::from_f64(float.number) {
+            Some(value) => self.last_value = Some(value),
+            None => self.fail(EvalError::UnsupportedFloat { span: float.token.span.clone() }),
+        }
+    }
+
+    // Look up the value bound to the variable, failing if it was never bound.
+    fn visit_variable(&mut self, variable: &super::ASTVariableExpression) {
+        match self.variables.get(variable.name()) {
+            Some(value) => self.last_value = Some(*value),
+            None => self.fail(EvalError::UndefinedVariable { span: variable.identifier.span.clone() }),
+        }
     }
 
-    // Implement the visit_binary_expression method to handle visiting a binary expression node in the AST.
+    // Evaluate the operand and apply the sign, failing if negation overflows.
+    fn visit_unary_expression(&mut self, expr: &super::ASTUnaryExpression) {
+        self.visit_expression(&expr.operand);
+        let Some(value) = self.last_value else { return };
+
+        match expr.operator.kind {
+            super::ASTUnaryOperatorKind::Identity => {}
+            super::ASTUnaryOperatorKind::Negate => match value.neg() {
+                Some(result) => self.last_value = Some(result),
+                None => self.fail(EvalError::Overflow { span: expr.operator.token.span.clone() }),
+            },
+        }
+    }
+
+    // Evaluate a binary expression along with the chain of binary expressions down its left operands,
+    // as in `1 + 2 + 3`, in a loop so a long chain can't exhaust the stack.
     fn visit_binary_expression(&mut self, expr: &super::ASTBinaryExpression) {
-        self.visit_expression(&expr.left); // Recursively visit the left-hand side of the binary expression.
-        let left = self.last_value.unwrap(); // Get the value of the left-hand side expression.
-
-        self.visit_expression(&expr.right); // Recursively visit the right-hand side of the binary expression.
-        let right = self.last_value.unwrap(); // Get the value of the right-hand side expression.
-
-        // Evaluate the binary expression based on the operator and update last_value with the result.
-        self.last_value = Some(match expr.operator.kind {
-            super::ASTBinaryOperatorKind::Plus => left + right,
-            super::ASTBinaryOperatorKind::Minus => left - right,
-            super::ASTBinaryOperatorKind::Multiply => left * right,
-            super::ASTBinaryOperatorKind::Divide => left / right,
-        });
+        let mut chain = vec![expr];
+        let mut leftmost: &super::ASTExpression = &expr.left;
+        while let super::ASTExpressionKind::Binary(inner) = &leftmost.kind {
+            self.steps += 1; // Count the binary expression as visit_expression would have.
+            chain.push(inner);
+            leftmost = &inner.left;
+        }
+        self.visit_expression(leftmost);
+
+        // Apply the operators innermost first, each to the value so far and its right-hand side.
+        for expr in chain.into_iter().rev() {
+            let Some(left) = self.last_value else { return };
+            self.visit_expression(&expr.right);
+            let Some(right) = self.last_value else { return };
+            self.apply_binary(expr, left, right);
+        }
+    }
+
+    // Evaluate the operand and compute its factorial, failing on negative or fractional operands and overflow.
+    fn visit_factorial_expression(&mut self, expr: &super::ASTFactorialExpression) {
+        self.visit_expression(&expr.expression);
+        let Some(value) = self.last_value else { return };
+
+        let span = expr.token.span.clone();
+        if value < N::from_i64(0) {
+            self.fail(EvalError::NegativeFactorial { span });
+            return;
+        }
+        if !value.is_integral() {
+            self.fail(EvalError::FractionalFactorial { span });
+            return;
+        }
+        match value.factorial() {
+            Some(result) => self.last_value = Some(result),
+            None => self.fail(EvalError::Overflow { span }),
+        }
+    }
+
+    // Evaluate the operand and take its absolute value, failing on overflow.
+    fn visit_absolute_expression(&mut self, expr: &super::ASTAbsoluteExpression) {
+        self.visit_expression(&expr.expression);
+        let Some(value) = self.last_value else { return };
+
+        match value.abs() {
+            Some(result) => self.last_value = Some(result),
+            None => self.fail(EvalError::Overflow { span: expr.token.span.clone() }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::numeric::Numeric;
+    use crate::ast::parser::Parser;
+    use crate::ast::Ast;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A backend that clamps results to the `i64` range instead of failing on overflow.
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    struct Saturating(i64);
+
+    impl Numeric for Saturating {
+        fn from_i64(value: i64) -> Self {
+            Saturating(value)
+        }
+
+        fn from_f64(_value: f64) -> Option<Self> {
+            None
+        }
+
+        fn add(self, other: Self) -> Option<Self> {
+            Some(Saturating(self.0.saturating_add(other.0)))
+        }
+
+        fn sub(self, other: Self) -> Option<Self> {
+            Some(Saturating(self.0.saturating_sub(other.0)))
+        }
+
+        fn mul(self, other: Self) -> Option<Self> {
+            Some(Saturating(self.0.saturating_mul(other.0)))
+        }
+
+        fn div(self, other: Self) -> Option<Self> {
+            Some(Saturating(self.0.saturating_div(other.0)))
+        }
+
+        fn rem(self, other: Self) -> Option<Self> {
+            self.0.checked_rem(other.0).map(Saturating)
+        }
+
+        fn divides_exactly(self, other: Self) -> bool {
+            self.0.divides_exactly(other.0)
+        }
+
+        fn pow(self, exponent: Self) -> Option<Self> {
+            let exponent = u32::try_from(exponent.0).ok()?;
+            Some(Saturating(self.0.saturating_pow(exponent)))
+        }
+
+        fn neg(self) -> Option<Self> {
+            Some(Saturating(self.0.saturating_neg()))
+        }
+
+        fn factorial(self) -> Option<Self> {
+            Some(Saturating((1..=self.0).fold(1i64, i64::saturating_mul)))
+        }
+
+        fn abs(self) -> Option<Self> {
+            Some(Saturating(self.0.saturating_abs()))
+        }
+    }
+
+    fn parse(input: &str) -> Ast {
+        let mut parser = Parser::new(Lexer::new(input).tokenize_all());
+        let mut ast = Ast::new();
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+        assert!(parser.errors().is_empty(), "unexpected errors: {:?}", parser.errors());
+        ast
+    }
+
+    #[test]
+    fn custom_backend_saturates_instead_of_overflowing() {
+        let mut evaluator = ASTEvaluator::<Saturating>::with_writer(Box::new(io::sink()));
+        parse("9223372036854775807 + 1").visit(&mut evaluator);
+        assert_eq!(evaluator.error, None);
+        assert_eq!(evaluator.last_value, Some(Saturating(i64::MAX)));
+        parse("-9223372036854775807 * 3").visit(&mut evaluator);
+        assert_eq!(evaluator.last_value, Some(Saturating(i64::MIN)));
+        parse("2 + 3 * 4").visit(&mut evaluator);
+        assert_eq!(evaluator.last_value, Some(Saturating(14)));
+    }
+
+    impl fmt::Display for Saturating {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    fn evaluate(input: &str) -> Result<Option<Value>, EvalError> {
+        parse(input).evaluate()
+    }
+
+    #[test]
+    fn modulo_gives_the_remainder() {
+        assert_eq!(evaluate("10 % 3"), Ok(Some(Value::Int(1))));
+        assert_eq!(evaluate("(4 + 6) % 4"), Ok(Some(Value::Int(2))));
+        assert!(matches!(evaluate("5 % (2 - 2)"), Err(EvalError::DivisionByZero { .. })));
+    }
+
+    #[test]
+    fn modulo_associates_left_with_multiply_and_divide() {
+        assert_eq!(evaluate("10 % 4 * 3"), Ok(Some(Value::Int(6))));
+        assert_eq!(evaluate("2 * 10 % 3"), Ok(Some(Value::Int(2))));
+        assert_eq!(evaluate("17 % 10 / 2"), Ok(Some(Value::Int(3))));
+        assert_eq!(evaluate("1 + 7 % 4"), Ok(Some(Value::Int(4))));
+    }
+
+    #[test]
+    fn let_binding_can_be_shadowed() {
+        assert_eq!(evaluate("let x = 1; let x = x + 1; x * 10"), Ok(Some(Value::Int(20))));
+    }
+
+    #[test]
+    fn undefined_variable_is_reported_by_name() {
+        match evaluate("let x = 1; x * y") {
+            Err(EvalError::UndefinedVariable { span }) => {
+                assert_eq!(span.literal(), "y");
+                assert_eq!(span.column(), 16);
+            }
+            result => panic!("expected an undefined variable, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn addition_overflow_is_reported_at_the_operator() {
+        match evaluate("9223372036854775807 + 1") {
+            Err(EvalError::Overflow { span }) => assert_eq!(span.column(), 21),
+            result => panic!("expected an overflow, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn multiplication_overflow_is_reported() {
+        assert!(matches!(evaluate("4611686018427387904 * 2"), Err(EvalError::Overflow { .. })));
+        assert!(matches!(evaluate("-9223372036854775807 - 2"), Err(EvalError::Overflow { .. })));
+    }
+
+    #[test]
+    fn arithmetic_within_range_is_exact() {
+        assert_eq!(evaluate("2 + 3 * 4"), Ok(Some(Value::Int(14))));
+        assert_eq!(evaluate("9223372036854775806 + 1"), Ok(Some(Value::Int(i64::MAX))));
+        assert_eq!(evaluate("(7 + 8) * 8 / 2 - 100"), Ok(Some(Value::Int(-40))));
+    }
+
+    #[test]
+    fn comparisons_evaluate_to_one_or_zero() {
+        assert_eq!(evaluate("3 < 5"), Ok(Some(Value::Int(1))));
+        assert_eq!(evaluate("5 < 3"), Ok(Some(Value::Int(0))));
+        assert_eq!(evaluate("5 == 5"), Ok(Some(Value::Int(1))));
+        assert_eq!(evaluate("5 != 5"), Ok(Some(Value::Int(0))));
+        assert_eq!(evaluate("2 >= 2.0"), Ok(Some(Value::Int(1))));
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_arithmetic() {
+        assert_eq!(evaluate("1 + 2 == 3"), Ok(Some(Value::Int(1))));
+        assert_eq!(evaluate("2 * 3 > 1 + 4"), Ok(Some(Value::Int(1))));
+    }
+
+    // A writer whose output the test can read back after handing it to the evaluator.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Evaluate the input, returning the error if any and everything it printed.
+    fn evaluate_printing(input: &str) -> (Option<EvalError>, String) {
+        let output = SharedBuffer::default();
+        let mut evaluator = ASTEvaluator::<Value>::with_writer(Box::new(output.clone()));
+        parse(input).visit(&mut evaluator);
+        let printed = String::from_utf8(output.0.borrow().clone()).expect("the output is UTF-8");
+        (evaluator.error, printed)
+    }
+
+    #[test]
+    fn division_by_zero_in_a_group_is_an_error() {
+        assert!(matches!(evaluate("10 / (5 - 5)"), Err(EvalError::DivisionByZero { .. })));
+    }
+
+    #[test]
+    fn evaluation_stops_at_the_first_error() {
+        assert!(matches!(evaluate("1 / 0; y"), Err(EvalError::DivisionByZero { .. })));
+        let (error, printed) = evaluate_printing("1 / 0; print(5)");
+        assert!(matches!(error, Some(EvalError::DivisionByZero { .. })));
+        assert_eq!(printed, "");
+    }
+
+    #[test]
+    fn power_evaluates_right_to_left_after_signs() {
+        assert_eq!(evaluate("-2 ** 2"), Ok(Some(Value::Int(-4))));
+        assert_eq!(evaluate("(-2) ** 2"), Ok(Some(Value::Int(4))));
+        assert_eq!(evaluate("2 ** 3 ** 2"), Ok(Some(Value::Int(512))));
+        assert!(matches!(evaluate("2 ** -1"), Err(EvalError::NegativeExponent { .. })));
+    }
+
+    #[test]
+    fn integers_become_floats_only_when_mixed_with_one() {
+        assert_eq!(evaluate("1 + 2.0"), Ok(Some(Value::Float(3.0))));
+        assert_eq!(evaluate("7 / 2"), Ok(Some(Value::Int(3))));
+        assert_eq!(evaluate("7.0 / 2"), Ok(Some(Value::Float(3.5))));
+    }
+
+    #[test]
+    fn float_result_that_is_not_a_number_is_an_invalid_operation() {
+        let error = evaluate("(-1.0) ** 0.5").unwrap_err();
+        assert!(matches!(error, EvalError::InvalidOperation { .. }), "{:?}", error);
+        assert_eq!(error.to_string(), "operation has no real result at 1:8");
+        assert!(matches!(evaluate("10.0 ** 400"), Err(EvalError::Overflow { .. })));
+    }
+
+    #[test]
+    fn integer_backend_rejects_float_literals() {
+        let mut evaluator = ASTEvaluator::<i64>::with_writer(Box::new(io::sink()));
+        parse("7 / 2").visit(&mut evaluator);
+        assert_eq!(evaluator.last_value, Some(3));
+        parse("1.5").visit(&mut evaluator);
+        assert!(matches!(evaluator.error, Some(EvalError::UnsupportedFloat { .. })));
     }
 }
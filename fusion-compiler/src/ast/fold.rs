@@ -0,0 +1,172 @@
+use super::evaluator::ASTEvaluator;
+use super::lexer::{TextSpan, Token, TokenKind};
+use super::value::Value;
+use super::{
+    ASTBinaryOperator, ASTExpression, ASTExpressionKind, ASTStatement, ASTStatementKind, ASTUnaryOperator, Ast,
+};
+
+// Return a copy of the AST with its constant sub-expressions replaced by their values, so
+// `2 + 3 * 4` becomes `14` and `x + 2 * 3` becomes `x + 6`.
+//
+// Folding works from the leaves up, and an operation is only folded once all of its operands are
+// literals. Variable references are left alone, as is any operation that would fail, such as
+// `1 / 0`, or would fail under strict mode, such as a division that truncates or one mixing an
+// integer with a float, so evaluating the folded AST reports the same errors.
+// Folded nodes keep the id and span of the expression they replace.
+pub fn fold_constants(ast: &Ast) -> Ast {
+    let mut folded = Ast::new();
+    for statement in &ast.statements {
+        folded.add_statement(fold_statement(statement));
+    }
+    folded
+}
+
+fn fold_statement(statement: &ASTStatement) -> ASTStatement {
+    let mut folded = match &statement.kind {
+        ASTStatementKind::Expression(expr) => ASTStatement::expression(fold_expression(expr)),
+        ASTStatementKind::Print(print) => ASTStatement::print(print.arguments.iter().map(fold_expression).collect()),
+        ASTStatementKind::Let(statement) => {
+            ASTStatement::let_statement(statement.identifier.clone(), fold_expression(&statement.initializer))
+        }
+    };
+    folded.id = statement.id;
+    folded
+}
+
+fn fold_expression(expression: &ASTExpression) -> ASTExpression {
+    let folded = match &expression.kind {
+        ASTExpressionKind::Number(number) => ASTExpression::number(number.number),
+        ASTExpressionKind::Float(float) => ASTExpression::float(float.number, float.token.clone()),
+        ASTExpressionKind::Variable(variable) => ASTExpression::variable(variable.identifier.clone()),
+        ASTExpressionKind::Unary(expr) => {
+            let operator = ASTUnaryOperator::new(expr.operator.kind, expr.operator.token.clone());
+            ASTExpression::unary(operator, fold_expression(&expr.operand))
+        }
+        ASTExpressionKind::Binary(_) => return fold_chain(expression),
+        ASTExpressionKind::Parenthesized(expr) => ASTExpression::paranthesized(fold_expression(&expr.expression)),
+        ASTExpressionKind::Factorial(expr) => {
+            ASTExpression::factorial(fold_expression(&expr.expression), expr.token.clone())
+        }
+        ASTExpressionKind::Absolute(expr) => {
+            ASTExpression::absolute(fold_expression(&expr.expression), expr.token.clone())
+        }
+    };
+    settle(expression, folded)
+}
+
+// Fold a binary expression along with the chain of binary expressions down its left operands, as
+// in `1 + 2 + 3`, in a loop so a long chain can't exhaust the stack.
+fn fold_chain(expression: &ASTExpression) -> ASTExpression {
+    let mut chain = Vec::new();
+    let mut leftmost = expression;
+    while let ASTExpressionKind::Binary(expr) = &leftmost.kind {
+        chain.push((leftmost, expr));
+        leftmost = &expr.left;
+    }
+    let mut folded = fold_expression(leftmost);
+    for (expression, expr) in chain.into_iter().rev() {
+        let operator = ASTBinaryOperator::new(expr.operator.kind, expr.operator.token.clone());
+        folded = settle(expression, ASTExpression::binary(operator, folded, fold_expression(&expr.right)));
+    }
+    folded
+}
+
+// Replace a rebuilt expression by its value if its operands are all literals, giving it the id and
+// span of the expression it was rebuilt from.
+fn settle(expression: &ASTExpression, mut folded: ASTExpression) -> ASTExpression {
+    if has_only_literal_operands(&folded) {
+        // Strict evaluation, so a division that would truncate, or an integer combined with a
+        // float, is left for the evaluator to judge
+        if let Ok(value) = ASTEvaluator::new().with_strict(true).try_evaluate(&folded) {
+            folded = literal(value, expression.span.as_ref());
+        }
+    }
+    folded.id = expression.id;
+    folded.span = expression.span.clone();
+    folded
+}
+
+// Whether the expression is an operation, or a group, whose operands are all literals.
+fn has_only_literal_operands(expression: &ASTExpression) -> bool {
+    match &expression.kind {
+        ASTExpressionKind::Number(_) | ASTExpressionKind::Float(_) | ASTExpressionKind::Variable(_) => false,
+        ASTExpressionKind::Unary(expr) => is_literal(&expr.operand),
+        ASTExpressionKind::Binary(expr) => is_literal(&expr.left) && is_literal(&expr.right),
+        ASTExpressionKind::Parenthesized(expr) => is_literal(&expr.expression),
+        ASTExpressionKind::Factorial(expr) => is_literal(&expr.expression),
+        ASTExpressionKind::Absolute(expr) => is_literal(&expr.expression),
+    }
+}
+
+fn is_literal(expression: &ASTExpression) -> bool {
+    matches!(expression.kind, ASTExpressionKind::Number(_) | ASTExpressionKind::Float(_))
+}
+
+// Build the literal node for a folded value. A float needs a token to print from, so one is
+// made from the value, placed where the folded expression started.
+fn literal(value: Value, span: Option<&TextSpan>) -> ASTExpression {
+    match value {
+        Value::Int(number) => ASTExpression::number(number),
+        Value::Float(number) => {
+            let text = value.to_string();
+            let (start, line, column) = span.map_or((0, 1, 1), |span| (span.start, span.line, span.column));
+            let span = TextSpan::new(start, start + text.len(), text).with_position(line, column);
+            ASTExpression::float(number, Token::new(TokenKind::Float(number), span))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+
+    fn parse(input: &str) -> Ast {
+        let mut parser = Parser::new(Lexer::new(input).tokenize_all());
+        let mut ast = Ast::new();
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+        assert!(parser.errors().is_empty(), "unexpected errors: {:?}", parser.errors());
+        ast
+    }
+
+    fn fold(input: &str) -> String {
+        fold_constants(&parse(input)).to_string()
+    }
+
+    #[test]
+    fn fully_constant_expression_folds_to_a_literal() {
+        assert_eq!(fold("2 + 3 * 4"), "14");
+        assert_eq!(fold("(1 + 2) * -3"), "-9");
+        assert_eq!(fold("1.5 * 2.0"), "3.0");
+    }
+
+    #[test]
+    fn partially_constant_expression_folds_its_constant_parts() {
+        assert_eq!(fold("x + 2 * 3"), "x + 6");
+        assert_eq!(fold("let y = 4 - 1; y * (2 + 3)"), "let y = 3; y * 5");
+    }
+
+    #[test]
+    fn operations_that_would_fail_are_left_alone() {
+        assert_eq!(fold("1 / 0 + 2 * 3"), "1 / 0 + 6");
+        assert_eq!(fold("7 / 2"), "7 / 2");
+        assert_eq!(fold("9223372036854775807 + 1"), "9223372036854775807 + 1");
+    }
+
+    #[test]
+    fn folded_nodes_keep_their_id_and_span() {
+        let ast = parse("1 + (2 * 3)");
+        let folded = fold_constants(&ast);
+        let (ASTStatementKind::Expression(original), ASTStatementKind::Expression(result)) =
+            (&ast.statements[0].kind, &folded.statements[0].kind)
+        else {
+            panic!("expected expression statements");
+        };
+        assert_eq!(result.id(), original.id());
+        assert_eq!(result.span(), original.span());
+        assert!(matches!(result.kind(), ASTExpressionKind::Number(number) if number.number == 7));
+    }
+}
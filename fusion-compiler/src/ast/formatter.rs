@@ -0,0 +1,225 @@
+use std::fmt;
+
+use super::{
+    ASTAbsoluteExpression, ASTBinaryExpression, ASTBinaryOperator, ASTBinaryOperatorKind, ASTExpression,
+    ASTExpressionKind, ASTFactorialExpression, ASTFloatExpression, ASTLetStatement, ASTNumberExpression,
+    ASTPrintStatement, ASTUnaryExpression, ASTUnaryOperatorKind, ASTVariableExpression, ASTVisitor, Associativity, Ast,
+    ParanthesizedExpression,
+};
+
+// Turns an AST back into source text in a canonical form: one space around binary operators,
+// statements separated by `; `, and parentheses only where precedence or associativity needs them.
+pub struct ASTFormatter {
+    output: String,
+}
+
+impl Default for ASTFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ASTFormatter {
+    pub fn new() -> Self {
+        Self { output: String::new() }
+    }
+
+    // Format every statement of the AST.
+    pub fn format(ast: &Ast) -> String {
+        let mut formatter = Self::new();
+        for (i, statement) in ast.statements.iter().enumerate() {
+            if i > 0 {
+                formatter.output.push_str("; ");
+            }
+            formatter.visit_statement(statement);
+        }
+        formatter.output
+    }
+
+    // Format a single expression.
+    pub fn format_expression(expression: &ASTExpression) -> String {
+        let mut formatter = Self::new();
+        formatter.visit_expression(expression);
+        formatter.output
+    }
+
+    // Format an operand of a binary operator, parenthesizing it when it is a binary expression that
+    // would otherwise bind differently: one with lower precedence, or one with the same precedence
+    // on the side the operator doesn't associate towards, as in `1 - (2 - 3)`. A sign on the left
+    // of `**` needs them too, as `-2 ** 2` negates the power.
+    fn visit_operand(&mut self, operand: &ASTExpression, parent: &ASTBinaryOperator, is_left: bool) {
+        self.visit_wrapped(operand, Self::needs_parens(operand, parent, is_left));
+    }
+
+    fn needs_parens(operand: &ASTExpression, parent: &ASTBinaryOperator, is_left: bool) -> bool {
+        match &Self::unwrap_parens(operand).kind {
+            ASTExpressionKind::Unary(_) => is_left && parent.kind == ASTBinaryOperatorKind::Power,
+            ASTExpressionKind::Binary(child) => {
+                let child_precedence = child.operator.precedence();
+                let parent_precedence = parent.precedence();
+                let associates_away = match parent.associativity() {
+                    Associativity::Left => !is_left,
+                    Associativity::Right => is_left,
+                };
+                child_precedence < parent_precedence || (child_precedence == parent_precedence && associates_away)
+            }
+            _ => false,
+        }
+    }
+
+    // Format an expression, surrounded by parentheses if asked to.
+    fn visit_wrapped(&mut self, expression: &ASTExpression, parenthesize: bool) {
+        if parenthesize {
+            self.output.push('(');
+        }
+        self.visit_expression(expression);
+        if parenthesize {
+            self.output.push(')');
+        }
+    }
+
+    // Look through any parentheses written in the source, which the formatter decides on afresh.
+    fn unwrap_parens(expression: &ASTExpression) -> &ASTExpression {
+        match &expression.kind {
+            ASTExpressionKind::Parenthesized(expr) => Self::unwrap_parens(&expr.expression),
+            _ => expression,
+        }
+    }
+}
+
+impl ASTVisitor for ASTFormatter {
+    fn visit_print_statement(&mut self, print_statement: &ASTPrintStatement) {
+        self.output.push_str("print(");
+        for (i, argument) in print_statement.arguments.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
+            }
+            self.visit_expression(argument);
+        }
+        self.output.push(')');
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.output.push_str(&format!("let {} = ", let_statement.name()));
+        self.visit_expression(&let_statement.initializer);
+    }
+
+    fn visit_number(&mut self, number: &ASTNumberExpression) {
+        self.output.push_str(&number.number.to_string());
+    }
+
+    // Floats keep their literal, so `2.50` isn't rewritten and `3.0` doesn't lose its decimal point.
+    fn visit_float(&mut self, float: &ASTFloatExpression) {
+        self.output.push_str(&float.token.span.literal);
+    }
+
+    fn visit_variable(&mut self, variable: &ASTVariableExpression) {
+        self.output.push_str(variable.name());
+    }
+
+    // A sign applies to a single operand, so anything larger is parenthesized.
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        self.output.push_str(match unary_expression.operator.kind {
+            ASTUnaryOperatorKind::Negate => "-",
+            ASTUnaryOperatorKind::Identity => "+",
+        });
+        let operand = Self::unwrap_parens(&unary_expression.operand);
+        match &operand.kind {
+            ASTExpressionKind::Binary(_) => self.visit_wrapped(operand, true),
+            // Keep repeated signs apart, so `- -5` doesn't run together
+            ASTExpressionKind::Unary(_) => {
+                self.output.push(' ');
+                self.visit_expression(operand);
+            }
+            _ => self.visit_expression(operand),
+        }
+    }
+
+    // The left operands of a chain like `1 + 2 + 3` that need no parentheses are gathered in a loop,
+    // so a long chain can't exhaust the stack.
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let mut chain = vec![binary_expression];
+        let mut parent = binary_expression;
+        while let ASTExpressionKind::Binary(child) = &Self::unwrap_parens(&parent.left).kind {
+            if Self::needs_parens(&parent.left, &parent.operator, true) {
+                break;
+            }
+            chain.push(child);
+            parent = child;
+        }
+        self.visit_operand(&parent.left, &parent.operator, true);
+        for expr in chain.into_iter().rev() {
+            self.output.push_str(&format!(" {} ", expr.operator.symbol()));
+            self.visit_operand(&expr.right, &expr.operator, false);
+        }
+    }
+
+    fn visit_parenthesized_expression(&mut self, parenthesized_expression: &ParanthesizedExpression) {
+        self.visit_expression(&parenthesized_expression.expression);
+    }
+
+    // Factorial binds tighter than any prefix or binary operator, so those need parentheses.
+    fn visit_factorial_expression(&mut self, factorial_expression: &ASTFactorialExpression) {
+        let operand = Self::unwrap_parens(&factorial_expression.expression);
+        let needs_parens = matches!(operand.kind, ASTExpressionKind::Binary(_) | ASTExpressionKind::Unary(_));
+        self.visit_wrapped(operand, needs_parens);
+        self.output.push('!');
+    }
+
+    fn visit_absolute_expression(&mut self, absolute_expression: &ASTAbsoluteExpression) {
+        self.output.push('|');
+        self.visit_expression(&absolute_expression.expression);
+        self.output.push('|');
+    }
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", ASTFormatter::format(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+
+    fn format(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input).tokenize_all());
+        let mut ast = Ast::new();
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+        assert!(parser.errors().is_empty(), "unexpected errors: {:?}", parser.errors());
+        ast.to_string()
+    }
+
+    #[test]
+    fn precedence_decides_which_parentheses_are_kept() {
+        assert_eq!(format("1 + 2 * 3"), "1 + 2 * 3");
+        assert_eq!(format("(1 + 2) * 3"), "(1 + 2) * 3");
+        assert_eq!(format("1 + (2 * 3)"), "1 + 2 * 3");
+        assert_eq!(format("((1))+2"), "1 + 2");
+    }
+
+    #[test]
+    fn associativity_decides_which_parentheses_are_kept() {
+        assert_eq!(format("(1 - 2) - 3"), "1 - 2 - 3");
+        assert_eq!(format("1 - (2 - 3)"), "1 - (2 - 3)");
+        assert_eq!(format("2 ** (3 ** 2)"), "2 ** 3 ** 2");
+        assert_eq!(format("(2 ** 3) ** 2"), "(2 ** 3) ** 2");
+    }
+
+    #[test]
+    fn statements_are_separated_by_semicolons() {
+        assert_eq!(format("let x=1;print(x,2)"), "let x = 1; print(x, 2)");
+    }
+
+    #[test]
+    fn sign_on_the_left_of_power_keeps_its_parentheses() {
+        assert_eq!(format("(-2) ** 2"), "(-2) ** 2");
+        assert_eq!(format("-2 ** 2"), "-(2 ** 2)");
+        assert_eq!(format("2 ** -2"), "2 ** -2");
+    }
+}
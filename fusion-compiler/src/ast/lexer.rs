@@ -1,40 +1,141 @@
+use std::fmt;
+
 /// The possible kinds of tokens that the lexer can generate.
 #[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenKind {
     Number(i64),
+    Float(f64),
+    Identifier(String),
     Plus,
     Minus,
     Asterisk,
+    DoubleAsterisk,
     Slash,
+    Percent,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Semicolon,
+    Bang,
+    Pipe,
+    Equals,
+    EqualsEquals,
+    BangEquals,
+    LessThan,
+    LessThanEquals,
+    GreaterThan,
+    GreaterThanEquals,
+    Print,
+    Let,
     Whitespace,
+    /// A `// line` or `/* block */` comment, which the parser skips like whitespace.
+    Comment,
+    /// A `//!` line comment holding a directive for the parser, such as `//!prec * 5`.
+    Pragma,
     EOF,
     Bad,
 }
 
+impl TokenKind {
+    /// Checks if this kind is an arithmetic or comparison operator, binary or postfix.
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Asterisk
+                | TokenKind::DoubleAsterisk
+                | TokenKind::Slash
+                | TokenKind::Percent
+                | TokenKind::Bang
+                | TokenKind::EqualsEquals
+                | TokenKind::BangEquals
+                | TokenKind::LessThan
+                | TokenKind::LessThanEquals
+                | TokenKind::GreaterThan
+                | TokenKind::GreaterThanEquals
+        )
+    }
+
+    /// Checks if this kind is a literal value.
+    pub fn is_literal(&self) -> bool {
+        matches!(self, TokenKind::Number(_) | TokenKind::Float(_))
+    }
+}
+
 /// Represents a span of text in the input string, including its start and end positions.
 #[derive(Debug, PartialEq, Clone)]
 pub struct TextSpan {
     pub(crate) start: usize,
     pub(crate) end: usize,
     pub(crate) literal: String,
+    /// The 1-based line of the span's start.
+    pub(crate) line: usize,
+    /// The 1-based column of the span's start, counted in characters.
+    pub(crate) column: usize,
 }
 
 impl TextSpan {
     /// Creates a new TextSpan with the provided start and end positions and the literal text.
+    /// The span is placed on line 1, column 1 until given a position with `with_position`.
     pub fn new(start: usize, end: usize, literal: String) -> Self {
-        Self {
-            start,
-            end,
-            literal,
-        }
+        Self { start, end, literal, line: 1, column: 1 }
+    }
+
+    /// Sets the line and column, both 1-based, where the span starts.
+    pub fn with_position(mut self, line: usize, column: usize) -> Self {
+        self.line = line;
+        self.column = column;
+        self
+    }
+
+    /// Returns the byte offset where the span starts.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the byte offset just past the end of the span.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Returns the text the span covers.
+    ///
+    /// Spans of whole expressions and statements leave this empty and record only their position;
+    /// slice the input with `start()..end()` to get their text.
+    pub fn literal(&self) -> &str {
+        &self.literal
+    }
+
+    /// Returns the 1-based line where the span starts.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the 1-based column, in characters, where the span starts.
+    pub fn column(&self) -> usize {
+        self.column
     }
 
     /// Calculates the length of the text span (number of characters).
     pub fn length(&self) -> usize {
         self.end - self.start
     }
+
+    /// Checks if the given byte offset falls within the span (start inclusive, end exclusive).
+    pub fn contains(&self, byte_offset: usize) -> bool {
+        self.start <= byte_offset && byte_offset < self.end
+    }
+}
+
+impl fmt::Display for TextSpan {
+    /// Formats the span's starting position as `line:column`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 /// Represents a token generated by the lexer, containing its kind and the associated text span.
@@ -49,53 +150,87 @@ impl Token {
     pub fn new(kind: TokenKind, span: TextSpan) -> Self {
         Self { kind, span }
     }
+
+    /// Returns the kind of the token.
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    /// Returns the span of input text the token covers.
+    pub fn span(&self) -> &TextSpan {
+        &self.span
+    }
+
+    /// Checks if the token carries no meaning for the grammar, such as whitespace or a comment.
+    /// Pragmas count as trivia too; the parser reads them separately.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, TokenKind::Whitespace | TokenKind::Comment | TokenKind::Pragma)
+    }
 }
 
 /// The lexer struct responsible for tokenizing the input string.
 pub struct Lexer<'a> {
     input: &'a str,
     current_pos: usize,
+    line: usize,
+    column: usize,
 }
 
 impl<'a> Lexer<'a> {
     /// Creates a new lexer instance with the provided input string.
+    ///
+    /// A leading UTF-8 byte order mark is skipped, as files saved by some editors begin with one.
+    /// Spans stay relative to the whole input, so the first token after a mark starts at byte 3.
     pub fn new(input: &'a str) -> Self {
-        Self {
-            input,
-            current_pos: 0,
-        }
+        let bom = if input.starts_with('\u{FEFF}') { '\u{FEFF}'.len_utf8() } else { 0 };
+        Self { input, current_pos: bom, line: 1, column: 1 }
+    }
+
+    /// Consumes the lexer and returns all of its tokens, ending with the `EOF` token.
+    pub fn tokenize_all(self) -> Vec<Token> {
+        self.collect()
     }
 
-    /// Gets the next token from the input string.
+    /// Gets the next token from the input string, or `None` once the `EOF` token has been returned.
     pub fn next_token(&mut self) -> Option<Token> {
         if self.current_pos == self.input.len() {
             let eof_char: char = '\0';
+            let end = self.input.len();
             self.current_pos += 1;
+            // The EOF token sits at the end of the input so diagnostics point past the last character.
             return Some(Token::new(
                 TokenKind::EOF,
-                TextSpan::new(0, 0, eof_char.to_string()),
+                TextSpan::new(end, end, eof_char.to_string()).with_position(self.line, self.column),
             ));
         }
 
         let c = self.current_char();
-        return c.map(|c| {
+        c.map(|c| {
             let start: usize = self.current_pos;
-            let mut kind = TokenKind::Bad;
-            if Self::is_number_start(&c) {
-                let number: i64 = self.consume_number();
-                kind = TokenKind::Number(number);
+            let (line, column) = (self.line, self.column);
+            let kind = if Self::is_number_start(&c) {
+                self.consume_number()
+            } else if c == '.' && self.peek_char().is_some_and(|c| c.is_ascii_digit()) {
+                // A leading dot (`.5`) is not a valid float; the whole literal becomes one `Bad` token.
+                self.consume();
+                self.consume_digits();
+                TokenKind::Bad
             } else if Self::is_whitespace(&c) {
                 self.consume();
-                kind = TokenKind::Whitespace;
+                TokenKind::Whitespace
+            } else if c == '/' && matches!(self.peek_char(), Some('/' | '*')) {
+                self.consume_comment()
+            } else if Self::is_word_start(&c) {
+                self.consume_word()
             } else {
-                kind = self.consume_punctuation();
-            }
+                self.consume_punctuation()
+            };
 
             let end = self.current_pos;
             let literal = self.input[start..end].to_string();
-            let span = TextSpan::new(start, end, literal);
+            let span = TextSpan::new(start, end, literal).with_position(line, column);
             Token::new(kind, span)
-        });
+        })
     }
 
     /// Consumes a punctuation character and returns its corresponding token kind.
@@ -104,17 +239,68 @@ impl<'a> Lexer<'a> {
         match c {
             '+' => TokenKind::Plus,
             '-' => TokenKind::Minus,
+            '*' if self.consume_if('*') => TokenKind::DoubleAsterisk,
             '*' => TokenKind::Asterisk,
             '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
             '(' => TokenKind::LeftParen,
             ')' => TokenKind::RightParen,
+            '[' => TokenKind::LeftBracket,
+            ']' => TokenKind::RightBracket,
+            ',' => TokenKind::Comma,
+            ';' => TokenKind::Semicolon,
+            '!' if self.consume_if('=') => TokenKind::BangEquals,
+            '!' => TokenKind::Bang,
+            '|' => TokenKind::Pipe,
+            '=' if self.consume_if('=') => TokenKind::EqualsEquals,
+            '=' => TokenKind::Equals,
+            '<' if self.consume_if('=') => TokenKind::LessThanEquals,
+            '<' => TokenKind::LessThan,
+            '>' if self.consume_if('=') => TokenKind::GreaterThanEquals,
+            '>' => TokenKind::GreaterThan,
             _ => TokenKind::Bad,
         }
     }
 
+    /// Consumes a `//` comment up to the end of the line, returning a pragma if it starts with `//!`,
+    /// or a `/* */` comment up to its first `*/`.
+    ///
+    /// Block comments don't nest: `/* a /* b */` is one comment, and a following `*/` is not part of it.
+    /// A block comment that is never closed runs to the end of the input as a single `Bad` token.
+    fn consume_comment(&mut self) -> TokenKind {
+        self.consume();
+        if self.consume() == Some('/') {
+            let is_pragma = self.consume_if('!');
+            while self.current_char().is_some_and(|c| c != '\n') {
+                self.consume();
+            }
+            return if is_pragma { TokenKind::Pragma } else { TokenKind::Comment };
+        }
+        while let Some(c) = self.consume() {
+            if c == '*' && self.consume_if('/') {
+                return TokenKind::Comment;
+            }
+        }
+        TokenKind::Bad
+    }
+
+    /// Consumes the current character if it is `expected`, completing a two-character operator.
+    fn consume_if(&mut self, expected: char) -> bool {
+        if self.current_char() != Some(expected) {
+            return false;
+        }
+        self.consume();
+        true
+    }
+
     /// Checks if the provided character is the start of a number.
     fn is_number_start(c: &char) -> bool {
-        c.is_digit(10)
+        c.is_ascii_digit()
+    }
+
+    /// Checks if the provided character can start a keyword or identifier.
+    fn is_word_start(c: &char) -> bool {
+        c.is_alphabetic() || *c == '_'
     }
 
     /// Checks if the provided character is a whitespace character.
@@ -122,39 +308,328 @@ impl<'a> Lexer<'a> {
         c.is_whitespace()
     }
 
-    /// Returns the current character at the lexer's current position.
+    /// Returns the character starting at the lexer's current byte position.
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.current_pos)
+        self.input.get(self.current_pos..)?.chars().next()
     }
 
-    /// Returns the next character after the lexer's current position.
-    fn peek_char(&mut self) -> Option<char> {
-        self.input.chars().nth(self.current_pos + 1)
+    /// Returns the character after the current one, however many bytes the current one takes.
+    fn peek_char(&self) -> Option<char> {
+        self.input.get(self.current_pos..)?.chars().nth(1)
     }
 
-    /// Consumes the current character and moves to the next position.
+    /// Consumes the current character, moving past all of its bytes and updating the line and column.
     fn consume(&mut self) -> Option<char> {
-        if self.current_pos >= self.input.len() {
-            return None;
+        let c = self.current_char()?;
+        self.current_pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
-        let c = self.current_char();
-        self.current_pos += 1;
 
-        c
+        Some(c)
     }
 
-    /// Consumes a sequence of digits and returns the parsed integer value.
-    fn consume_number(&mut self) -> i64 {
-        let mut number: i64 = 0;
+    /// Consumes a word and returns the keyword it names, or an identifier for any other word.
+    ///
+    /// Digits behind a leading underscore, as in `_5`, are a malformed number rather than a name.
+    fn consume_word(&mut self) -> TokenKind {
+        let start = self.current_pos;
         while let Some(c) = self.current_char() {
-            if c.is_digit(10) {
+            if c.is_alphanumeric() || c == '_' {
                 self.consume().unwrap();
-                number = number * 10 + c.to_digit(10).unwrap() as i64;
             } else {
                 break;
             }
         }
 
-        number
+        match &self.input[start..self.current_pos] {
+            "print" => TokenKind::Print,
+            "let" => TokenKind::Let,
+            name if name.trim_start_matches('_').starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c == '_' || c.is_ascii_digit()) =>
+            {
+                TokenKind::Bad
+            }
+            name => TokenKind::Identifier(name.to_string()),
+        }
+    }
+
+    /// Consumes a number literal and returns its token kind.
+    ///
+    /// A literal is either an integer (`42`) or a float with digits on both sides of the
+    /// decimal point (`3.14`). Single underscores may separate digits, as in `1_000_000`.
+    /// A trailing dot (`5.`), a second decimal point (`1.2.3`) or a misplaced underscore
+    /// (`5_`, `5__0`) makes the whole literal a single `Bad` token, as does an integer too
+    /// large for an `i64`. Integers may also be written in hexadecimal (`0xFF`), octal (`0o17`)
+    /// or binary (`0b1010`).
+    fn consume_number(&mut self) -> TokenKind {
+        let start = self.current_pos;
+        if self.current_char() == Some('0') {
+            if let Some(radix) = self.peek_char().and_then(Self::radix_of_prefix) {
+                return self.consume_radix_number(radix);
+            }
+        }
+        let integer_well_formed = self.consume_digits();
+
+        if self.current_char() != Some('.') {
+            if !integer_well_formed {
+                return TokenKind::Bad;
+            }
+            // An integer too large for an i64 is rejected whole rather than wrapped.
+            let number = self.input[start..self.current_pos]
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .try_fold(0i64, |n, digit| n.checked_mul(10)?.checked_add(digit as i64));
+            return number.map_or(TokenKind::Bad, TokenKind::Number);
+        }
+        self.consume(); // The decimal point.
+        if !self.consume_digits() || !integer_well_formed {
+            return TokenKind::Bad;
+        }
+        if self.current_char() == Some('.') {
+            while self.current_char().is_some_and(|c| c == '.' || c == '_' || c.is_ascii_digit()) {
+                self.consume();
+            }
+            return TokenKind::Bad;
+        }
+
+        // A float too large for an f64 is rejected rather than read as infinity.
+        let literal = self.input[start..self.current_pos].replace('_', "");
+        Some(literal.parse().unwrap())
+            .filter(|number: &f64| number.is_finite())
+            .map_or(TokenKind::Bad, TokenKind::Float)
+    }
+
+    /// Returns the radix named by the letter after a leading `0`, as in `0x`, `0o` and `0b`.
+    fn radix_of_prefix(c: char) -> Option<u32> {
+        match c {
+            'x' => Some(16),
+            'o' => Some(8),
+            'b' => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Consumes an integer literal with a radix prefix, such as `0xFF`.
+    ///
+    /// Everything up to the next character that can't continue a word is part of the literal, so
+    /// a digit outside the radix (`0b2`) or no digits at all (`0x`) make the whole literal `Bad`.
+    fn consume_radix_number(&mut self, radix: u32) -> TokenKind {
+        self.consume(); // The `0`.
+        self.consume(); // The radix letter.
+        let start = self.current_pos;
+        while self.current_char().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.consume();
+        }
+        let digits = &self.input[start..self.current_pos];
+        if !digits.split('_').all(|group| !group.is_empty() && group.chars().all(|c| c.is_digit(radix))) {
+            return TokenKind::Bad;
+        }
+        let number = digits
+            .chars()
+            .filter_map(|c| c.to_digit(radix))
+            .try_fold(0i64, |n, digit| n.checked_mul(radix as i64)?.checked_add(digit as i64));
+        number.map_or(TokenKind::Bad, TokenKind::Number)
+    }
+
+    /// Consumes a run of digits and underscores, returning whether it is a well-formed run:
+    /// at least one digit, with each underscore between two digits.
+    fn consume_digits(&mut self) -> bool {
+        let mut previous = None;
+        let mut well_formed = true;
+        while let Some(c) = self.current_char().filter(|c| c.is_ascii_digit() || *c == '_') {
+            if c == '_' && !previous.is_some_and(|p: char| p.is_ascii_digit()) {
+                well_formed = false; // A leading or doubled underscore.
+            }
+            self.consume();
+            previous = Some(c);
+        }
+        well_formed && previous.is_some_and(|c| c != '_')
+    }
+}
+
+/// Yields the same tokens as repeated calls to `next_token`, ending after the `EOF` token.
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eof_span_sits_at_the_end_of_the_input() {
+        for input in ["", "1 + 2", "x // comment", "€"] {
+            let eof = Lexer::new(input).tokenize_all().pop().unwrap();
+            assert_eq!(eof.kind, TokenKind::EOF);
+            assert_eq!((eof.span.start, eof.span.end), (input.len(), input.len()), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn token_kind_predicates_sort_kinds_into_categories() {
+        let operators = [
+            TokenKind::Plus,
+            TokenKind::DoubleAsterisk,
+            TokenKind::Percent,
+            TokenKind::Bang,
+            TokenKind::LessThanEquals,
+        ];
+        for operator in operators {
+            assert!(operator.is_operator(), "{:?}", operator);
+            assert!(!operator.is_literal(), "{:?}", operator);
+        }
+        for literal in [TokenKind::Number(1), TokenKind::Float(1.5)] {
+            assert!(literal.is_literal(), "{:?}", literal);
+            assert!(!literal.is_operator(), "{:?}", literal);
+        }
+        for other in [TokenKind::LeftParen, TokenKind::Identifier("x".to_string()), TokenKind::Bad, TokenKind::EOF] {
+            assert!(!other.is_operator() && !other.is_literal(), "{:?}", other);
+        }
+    }
+
+    #[test]
+    fn whitespace_comments_and_pragmas_are_trivia() {
+        let trivia: Vec<bool> = Lexer::new("1 //!prec + 5\n/* c */ x").map(|token| token.is_trivia()).collect();
+        assert_eq!(trivia, [false, true, true, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn tokenize_all_matches_a_manual_loop() {
+        let input = "let x = 1.5 * (2 + 3) // done";
+        let mut lexer = Lexer::new(input);
+        let mut manual = Vec::new();
+        while let Some(token) = lexer.next_token() {
+            manual.push(token);
+        }
+        assert_eq!(Lexer::new(input).tokenize_all(), manual);
+    }
+
+    #[test]
+    fn non_ascii_character_is_one_bad_token() {
+        let input = "1 + € + 2";
+        let tokens = Lexer::new(input).tokenize_all();
+        assert_eq!(tokens[4].kind, TokenKind::Bad);
+        assert_eq!(&input[tokens[4].span.start..tokens[4].span.end], "€");
+        assert_eq!(tokens[8].kind, TokenKind::Number(2));
+        assert_eq!(tokens[8].span.column(), 9);
+    }
+
+    #[test]
+    fn column_counts_from_the_start_of_the_line() {
+        let tokens = Lexer::new("1 +\n  23").tokenize_all();
+        let number = tokens.iter().find(|token| token.kind == TokenKind::Number(23)).unwrap();
+        assert_eq!((number.span.line(), number.span.column()), (2, 3));
+        assert_eq!(number.span.to_string(), "2:3");
+    }
+
+    #[test]
+    fn iterator_ends_after_a_single_eof() {
+        let tokens: Vec<Token> = Lexer::new("1 + 2").collect();
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::EOF);
+        let mut lexer = Lexer::new("1");
+        assert_eq!(lexer.by_ref().count(), 2);
+        assert!(lexer.next().is_none());
+    }
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Lexer::new(input).filter(|token| !token.is_trivia()).map(|token| token.kind).collect()
+    }
+
+    #[test]
+    fn comments_are_trivia() {
+        let expected = vec![TokenKind::Number(1), TokenKind::Plus, TokenKind::Number(2), TokenKind::EOF];
+        assert_eq!(kinds("1 + 2 // ignored"), expected);
+        assert_eq!(kinds("1 /* mid */ + 2"), expected);
+        assert_eq!(Lexer::new("1 // ignored").tokenize_all()[2].kind, TokenKind::Comment);
+        assert_eq!(crate::evaluate("1 /* mid */ + 2 // ignored"), Ok(Some(crate::Value::Int(3))));
+    }
+
+    #[test]
+    fn unterminated_block_comment_is_a_bad_token() {
+        let tokens = Lexer::new("1 /* never closed").tokenize_all();
+        assert_eq!(tokens[2].kind, TokenKind::Bad);
+        assert_eq!(tokens[2].span.literal, "/* never closed");
+        let error = crate::evaluate("1 /* never closed").unwrap_err();
+        assert!(error.to_string().contains("unterminated block comment"), "{}", error);
+    }
+
+    #[test]
+    fn integer_too_large_for_i64_is_a_bad_token() {
+        let tokens = Lexer::new("1234567890123456789012345 + 1").tokenize_all();
+        assert_eq!(tokens[0].kind, TokenKind::Bad);
+        assert_eq!((tokens[0].span.start, tokens[0].span.end), (0, 25));
+        assert_eq!(kinds("9223372036854775807"), vec![TokenKind::Number(i64::MAX), TokenKind::EOF]);
+        assert_eq!(kinds("9223372036854775808"), vec![TokenKind::Bad, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn float_too_large_for_f64_is_a_bad_token() {
+        let literal = "1".repeat(400) + ".0";
+        assert_eq!(kinds(&literal), vec![TokenKind::Bad, TokenKind::EOF]);
+        let error = crate::evaluate(&literal).unwrap_err();
+        assert!(error.to_string().starts_with("float literal `111"), "{}", error);
+        assert!(matches!(kinds(&("1".repeat(300) + ".0"))[0], TokenKind::Float(_)));
+    }
+
+    #[test]
+    fn underscores_may_separate_digits() {
+        assert_eq!(kinds("1_000_000"), vec![TokenKind::Number(1_000_000), TokenKind::EOF]);
+        assert_eq!(kinds("1_000.2_5"), vec![TokenKind::Float(1000.25), TokenKind::EOF]);
+        assert_eq!(kinds("5_"), vec![TokenKind::Bad, TokenKind::EOF]);
+        assert_eq!(kinds("5__0"), vec![TokenKind::Bad, TokenKind::EOF]);
+        assert_eq!(kinds("_5"), vec![TokenKind::Bad, TokenKind::EOF]);
+        assert_eq!(kinds("_5x"), vec![TokenKind::Identifier("_5x".to_string()), TokenKind::EOF]);
+        let error = crate::evaluate("_5").unwrap_err();
+        assert_eq!(error.to_string(), "malformed number literal `_5` at 1:1");
+    }
+
+    #[test]
+    fn radix_prefixes_select_the_digit_set() {
+        assert_eq!(kinds("0xFF"), vec![TokenKind::Number(255), TokenKind::EOF]);
+        assert_eq!(kinds("0o17"), vec![TokenKind::Number(15), TokenKind::EOF]);
+        assert_eq!(kinds("0b1010"), vec![TokenKind::Number(10), TokenKind::EOF]);
+        assert_eq!(kinds("0"), vec![TokenKind::Number(0), TokenKind::EOF]);
+        assert_eq!(kinds("0x"), vec![TokenKind::Bad, TokenKind::EOF]);
+        assert_eq!(kinds("0b2"), vec![TokenKind::Bad, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn span_contains_offsets_from_its_start_up_to_its_end() {
+        let span = TextSpan::new(2, 5, "abc".to_string());
+        assert!(!span.contains(1));
+        assert!(span.contains(2));
+        assert!(span.contains(4));
+        assert!(!span.contains(5));
+        assert!(!TextSpan::new(3, 3, String::new()).contains(3));
+    }
+
+    #[test]
+    fn token_getters_expose_kind_and_span() {
+        let tokens = Lexer::new("12 + x").tokenize_all();
+        let plus = &tokens[2];
+        assert_eq!(plus.kind(), &TokenKind::Plus);
+        assert_eq!(plus.span().start(), 3);
+        assert_eq!(plus.span().end(), 4);
+        assert_eq!(plus.span().literal(), "+");
+        assert_eq!(tokens[4].span().literal(), "x");
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped_without_shifting_offsets() {
+        let input = "\u{FEFF}1 + 2";
+        let tokens = Lexer::new(input).tokenize_all();
+        assert_eq!(tokens[0].kind(), &TokenKind::Number(1));
+        assert_eq!((tokens[0].span().start(), tokens[0].span().column()), (3, 1));
+        assert_eq!(&input[tokens[2].span().start()..tokens[2].span().end()], "+");
+        assert_eq!(tokens.last().unwrap().span().start(), input.len());
     }
 }
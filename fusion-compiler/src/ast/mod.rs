@@ -1,18 +1,28 @@
-use self::lexer::Token;
+use self::lexer::{TextSpan, Token};
 
+pub mod diagnostics;
+pub mod evaluator;
+pub mod fold;
+pub mod formatter;
 pub mod lexer;
+pub mod numeric;
 pub mod parser;
-pub mod evaluator;
+pub mod value;
+pub mod walk;
 
 pub struct Ast {
     pub statements: Vec<ASTStatement>,
 }
 
+impl Default for Ast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ast {
     pub fn new() -> Self {
-        Self {
-            statements: Vec::new(),
-        }
+        Self { statements: Vec::new() }
     }
 
     pub fn add_statement(&mut self, statement: ASTStatement) {
@@ -25,7 +35,43 @@ impl Ast {
         }
     }
 
-    pub fn visualize(&mut self) -> () {
+    /// Runs the tree-walking evaluator and returns the value of the last statement.
+    ///
+    /// ```
+    /// use fusion_compiler::ast::{lexer::Lexer, parser::Parser, value::Value, Ast};
+    ///
+    /// let tokens = Lexer::new("(7 + 8) * 8 / 2").tokenize_all();
+    ///
+    /// let mut ast = Ast::new();
+    /// let mut parser = Parser::new(tokens);
+    /// while let Some(statement) = parser.next_statement() {
+    ///     ast.add_statement(statement);
+    /// }
+    ///
+    /// assert_eq!(ast.evaluate(), Ok(Some(Value::Int(60))));
+    /// ```
+    pub fn evaluate(&self) -> Result<Option<value::Value>, evaluator::EvalError> {
+        let mut evaluator = evaluator::ASTEvaluator::new();
+        for statement in &self.statements {
+            evaluator.visit_statement(statement);
+        }
+        match evaluator.error {
+            Some(error) => Err(error),
+            None => Ok(evaluator.last_value),
+        }
+    }
+
+    /// Returns the innermost expression whose span contains the byte offset, or None if no
+    /// expression does, as for an offset past the end of the input.
+    pub fn node_at(&self, offset: usize) -> Option<walk::NodeRef<'_>> {
+        // Pre-order lists each expression before the ones inside it, so the last match is innermost
+        walk::walk_preorder(self).into_iter().rfind(|node| match node {
+            walk::NodeRef::Expression(expression) => expression.span().is_some_and(|span| span.contains(offset)),
+            walk::NodeRef::Statement(_) => false,
+        })
+    }
+
+    pub fn visualize(&mut self) {
         let mut printer = ASTPrinter { indent: 0 };
         self.visit(&mut printer);
         // printer.output
@@ -47,6 +93,22 @@ pub trait ASTVisitor {
             ASTStatementKind::Expression(expr) => {
                 self.visit_expression(expr);
             }
+            ASTStatementKind::Print(print) => {
+                self.visit_print_statement(print);
+            }
+            ASTStatementKind::Let(statement) => {
+                self.visit_let_statement(statement);
+            }
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+    }
+
+    fn visit_print_statement(&mut self, print_statement: &ASTPrintStatement) {
+        for argument in &print_statement.arguments {
+            self.visit_expression(argument);
         }
     }
 
@@ -59,12 +121,25 @@ pub trait ASTVisitor {
             ASTExpressionKind::Number(number) => {
                 self.visit_number(number);
             }
+            ASTExpressionKind::Float(float) => {
+                self.visit_float(float);
+            }
+            ASTExpressionKind::Variable(variable) => {
+                self.visit_variable(variable);
+            }
+            ASTExpressionKind::Unary(expr) => {
+                self.visit_unary_expression(expr);
+            }
             ASTExpressionKind::Binary(expr) => {
-                self.visit_binary_expression(&expr);
+                self.visit_binary_expression(expr);
+            }
+            ASTExpressionKind::Parenthesized(expr) => self.visit_parenthesized_expression(expr),
+            ASTExpressionKind::Factorial(expr) => {
+                self.visit_factorial_expression(expr);
+            }
+            ASTExpressionKind::Absolute(expr) => {
+                self.visit_absolute_expression(expr);
             }
-            ASTExpressionKind::Parenthesized(expr) =>{
-                self.visit_parenthesized_expression(expr)
-            },
         }
     }
 
@@ -74,14 +149,30 @@ pub trait ASTVisitor {
 
     fn visit_number(&mut self, number: &ASTNumberExpression);
 
+    fn visit_float(&mut self, float: &ASTFloatExpression);
+
+    fn visit_variable(&mut self, variable: &ASTVariableExpression);
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        self.visit_expression(&unary_expression.operand)
+    }
+
     fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
         self.visit_expression(&binary_expression.left);
         self.visit_expression(&binary_expression.right);
     }
 
-    fn visit_parenthesized_expression(&mut self, parenthesized_expression: &ParanthesizedExpression){
+    fn visit_parenthesized_expression(&mut self, parenthesized_expression: &ParanthesizedExpression) {
         self.visit_expression(&parenthesized_expression.expression)
     }
+
+    fn visit_factorial_expression(&mut self, factorial_expression: &ASTFactorialExpression) {
+        self.visit_expression(&factorial_expression.expression)
+    }
+
+    fn visit_absolute_expression(&mut self, absolute_expression: &ASTAbsoluteExpression) {
+        self.visit_expression(&absolute_expression.expression)
+    }
 }
 
 pub struct ASTPrinter {
@@ -105,25 +196,84 @@ impl ASTVisitor for ASTPrinter {
         self.indent -= LEVEL_INDENT;
     }
 
+    fn visit_print_statement(&mut self, print_statement: &ASTPrintStatement) {
+        self.print_with_indent("Print Statement:");
+        self.indent += LEVEL_INDENT;
+        for argument in &print_statement.arguments {
+            self.visit_expression(argument);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.print_with_indent(&format!("Let Statement: {}", let_statement.name()));
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&let_statement.initializer);
+        self.indent -= LEVEL_INDENT;
+    }
+
     fn visit_number(&mut self, number: &ASTNumberExpression) {
         self.print_with_indent(&format!("Number: {}", number.number));
     }
 
-    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
-        self.print_with_indent("Binary Expression.");
+    fn visit_float(&mut self, float: &ASTFloatExpression) {
+        self.print_with_indent(&format!("Float: {}", float.number));
+    }
+
+    fn visit_variable(&mut self, variable: &ASTVariableExpression) {
+        self.print_with_indent(&format!("Variable: {}", variable.name()));
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        self.print_with_indent("Unary Expression:");
         self.indent += LEVEL_INDENT;
-        self.print_with_indent(&format!("Operator: {:?}", binary_expression.operator.kind));
-        self.visit_expression(&binary_expression.left);
-        self.visit_expression(&binary_expression.right);
+        self.print_with_indent(&format!("Operator: {:?}", unary_expression.operator.kind));
+        self.visit_expression(&unary_expression.operand);
         self.indent -= LEVEL_INDENT;
     }
 
+    // Print the binary expressions down the left operands of a chain like `1 + 2 + 3` in a loop,
+    // so a long chain can't exhaust the stack, then their right operands, innermost first
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let mut chain = Vec::new();
+        let mut expr = binary_expression;
+        loop {
+            self.print_with_indent("Binary Expression.");
+            self.indent += LEVEL_INDENT;
+            self.print_with_indent(&format!("Operator: {}", expr.operator.symbol()));
+            chain.push(expr);
+            match &expr.left.kind {
+                ASTExpressionKind::Binary(left) => expr = left,
+                _ => break,
+            }
+        }
+        self.visit_expression(&expr.left);
+        for expr in chain.into_iter().rev() {
+            self.visit_expression(&expr.right);
+            self.indent -= LEVEL_INDENT;
+        }
+    }
+
     fn visit_parenthesized_expression(&mut self, parenthesized_expression: &ParanthesizedExpression) {
         self.print_with_indent("Parenthesized Expression:");
         self.indent += LEVEL_INDENT;
         self.visit_expression(&parenthesized_expression.expression);
         self.indent -= LEVEL_INDENT;
     }
+
+    fn visit_factorial_expression(&mut self, factorial_expression: &ASTFactorialExpression) {
+        self.print_with_indent("Factorial Expression:");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&factorial_expression.expression);
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_absolute_expression(&mut self, absolute_expression: &ASTAbsoluteExpression) {
+        self.print_with_indent("Absolute Expression:");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&absolute_expression.expression);
+        self.indent -= LEVEL_INDENT;
+    }
 }
 
 impl ASTPrinter {
@@ -134,34 +284,112 @@ impl ASTPrinter {
 
 pub enum ASTStatementKind {
     Expression(ASTExpression),
+    Print(ASTPrintStatement),
+    Let(ASTLetStatement),
+}
+
+pub struct ASTPrintStatement {
+    arguments: Vec<ASTExpression>,
+}
+
+pub struct ASTLetStatement {
+    identifier: Token,
+    initializer: ASTExpression,
 }
 
+impl ASTLetStatement {
+    pub fn name(&self) -> &str {
+        &self.identifier.span.literal
+    }
+}
+
+// Identifies a node within its AST. The parser numbers statements and expressions in pre-order,
+// continuing across statements; nodes built outside the parser keep the default id.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+pub struct NodeId(pub usize);
+
 pub struct ASTStatement {
     kind: ASTStatementKind,
+    id: NodeId,
 }
 
 impl ASTStatement {
     pub fn new(kind: ASTStatementKind) -> Self {
-        ASTStatement { kind }
+        ASTStatement { kind, id: NodeId::default() }
     }
 
     pub fn expression(expr: ASTExpression) -> Self {
         ASTStatement::new(ASTStatementKind::Expression(expr))
     }
+
+    pub fn print(arguments: Vec<ASTExpression>) -> Self {
+        ASTStatement::new(ASTStatementKind::Print(ASTPrintStatement { arguments }))
+    }
+
+    pub fn let_statement(identifier: Token, initializer: ASTExpression) -> Self {
+        ASTStatement::new(ASTStatementKind::Let(ASTLetStatement { identifier, initializer }))
+    }
+
+    pub fn kind(&self) -> &ASTStatementKind {
+        &self.kind
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
 }
 
 pub enum ASTExpressionKind {
     Number(ASTNumberExpression),
+    Float(ASTFloatExpression),
+    Variable(ASTVariableExpression),
+    Unary(ASTUnaryExpression),
     Binary(ASTBinaryExpression),
     Parenthesized(ParanthesizedExpression),
+    Factorial(ASTFactorialExpression),
+    Absolute(ASTAbsoluteExpression),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ASTBinaryOperatorKind {
     Plus,
     Minus,
     Multiply,
     Divide,
+    Modulo,
+    Power,
+    Equals,
+    NotEquals,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl ASTBinaryOperatorKind {
+    // The default precedence of the operator; higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            ASTBinaryOperatorKind::Equals => 1,
+            ASTBinaryOperatorKind::NotEquals => 1,
+            ASTBinaryOperatorKind::LessThan => 2,
+            ASTBinaryOperatorKind::LessThanOrEqual => 2,
+            ASTBinaryOperatorKind::GreaterThan => 2,
+            ASTBinaryOperatorKind::GreaterThanOrEqual => 2,
+            ASTBinaryOperatorKind::Plus => 3,
+            ASTBinaryOperatorKind::Minus => 3,
+            ASTBinaryOperatorKind::Multiply => 4,
+            ASTBinaryOperatorKind::Divide => 4,
+            ASTBinaryOperatorKind::Modulo => 4,
+            ASTBinaryOperatorKind::Power => 5,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
 }
 
 pub struct ASTBinaryOperator {
@@ -175,42 +403,180 @@ impl ASTBinaryOperator {
     }
 
     pub fn precedence(&self) -> u8 {
+        self.kind.precedence()
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self.kind {
+            ASTBinaryOperatorKind::Plus => "+",
+            ASTBinaryOperatorKind::Minus => "-",
+            ASTBinaryOperatorKind::Multiply => "*",
+            ASTBinaryOperatorKind::Divide => "/",
+            ASTBinaryOperatorKind::Modulo => "%",
+            ASTBinaryOperatorKind::Power => "**",
+            ASTBinaryOperatorKind::Equals => "==",
+            ASTBinaryOperatorKind::NotEquals => "!=",
+            ASTBinaryOperatorKind::LessThan => "<",
+            ASTBinaryOperatorKind::LessThanOrEqual => "<=",
+            ASTBinaryOperatorKind::GreaterThan => ">",
+            ASTBinaryOperatorKind::GreaterThanOrEqual => ">=",
+        }
+    }
+
+    pub fn associativity(&self) -> Associativity {
         match self.kind {
-            ASTBinaryOperatorKind::Plus => 1,
-            ASTBinaryOperatorKind::Minus => 1,
-            ASTBinaryOperatorKind::Multiply => 2,
-            ASTBinaryOperatorKind::Divide => 2,
+            ASTBinaryOperatorKind::Plus
+            | ASTBinaryOperatorKind::Minus
+            | ASTBinaryOperatorKind::Multiply
+            | ASTBinaryOperatorKind::Divide
+            | ASTBinaryOperatorKind::Modulo
+            | ASTBinaryOperatorKind::Equals
+            | ASTBinaryOperatorKind::NotEquals
+            | ASTBinaryOperatorKind::LessThan
+            | ASTBinaryOperatorKind::LessThanOrEqual
+            | ASTBinaryOperatorKind::GreaterThan
+            | ASTBinaryOperatorKind::GreaterThanOrEqual => Associativity::Left,
+            ASTBinaryOperatorKind::Power => Associativity::Right,
         }
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ASTUnaryOperatorKind {
+    Negate,
+    Identity,
+}
+
+pub struct ASTUnaryOperator {
+    kind: ASTUnaryOperatorKind,
+    token: Token,
+}
+
+impl ASTUnaryOperator {
+    pub fn new(kind: ASTUnaryOperatorKind, token: Token) -> Self {
+        ASTUnaryOperator { kind, token }
+    }
+}
+
+pub struct ASTUnaryExpression {
+    operator: ASTUnaryOperator,
+    operand: Box<ASTExpression>,
+}
+
 pub struct ASTBinaryExpression {
     left: Box<ASTExpression>,
     operator: ASTBinaryOperator,
     right: Box<ASTExpression>,
 }
 
+impl ASTBinaryExpression {
+    // Detach the left operand if it is itself a binary expression, leaving a literal in its place
+    fn take_binary_left(&mut self) -> Option<Box<ASTExpression>> {
+        matches!(self.left.kind, ASTExpressionKind::Binary(_))
+            .then(|| std::mem::replace(&mut self.left, Box::new(ASTExpression::number(0))))
+    }
+}
+
 pub struct ASTNumberExpression {
     number: i64,
 }
 
+pub struct ASTFloatExpression {
+    number: f64,
+    token: Token,
+}
+
+pub struct ASTVariableExpression {
+    identifier: Token,
+}
+
+impl ASTVariableExpression {
+    pub fn name(&self) -> &str {
+        &self.identifier.span.literal
+    }
+}
+
 pub struct ParanthesizedExpression {
     expression: Box<ASTExpression>,
 }
 
+pub struct ASTFactorialExpression {
+    expression: Box<ASTExpression>,
+    token: Token,
+}
+
+pub struct ASTAbsoluteExpression {
+    expression: Box<ASTExpression>,
+    token: Token,
+}
+
 pub struct ASTExpression {
     kind: ASTExpressionKind,
+    id: NodeId,
+    // The source text the expression was parsed from; None for nodes built outside the parser.
+    span: Option<TextSpan>,
+    // How deeply walking the expression recurses; see `height()`.
+    height: usize,
 }
 
 impl ASTExpression {
     pub fn new(kind: ASTExpressionKind) -> Self {
-        ASTExpression { kind }
+        let height = match &kind {
+            ASTExpressionKind::Number(_) | ASTExpressionKind::Float(_) | ASTExpressionKind::Variable(_) => 1,
+            ASTExpressionKind::Unary(expr) => 1 + expr.operand.height,
+            // The left operand is walked in the same loop as the expression itself
+            ASTExpressionKind::Binary(expr) => expr.left.height.max(1 + expr.right.height),
+            ASTExpressionKind::Parenthesized(expr) => 1 + expr.expression.height,
+            ASTExpressionKind::Factorial(expr) => 1 + expr.expression.height,
+            ASTExpressionKind::Absolute(expr) => 1 + expr.expression.height,
+        };
+        ASTExpression { kind, id: NodeId::default(), span: None, height }
+    }
+
+    pub fn kind(&self) -> &ASTExpressionKind {
+        &self.kind
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    // The span covering the whole expression, including any parentheses around its operands.
+    // It holds only the position; slice the input with `start()..end()` for the text.
+    pub fn span(&self) -> Option<&TextSpan> {
+        self.span.as_ref()
+    }
+
+    // How deeply the expression nests: 1 for a literal or variable, one more than its operand for
+    // anything but a binary expression, and for a binary expression the deeper of its left operand
+    // and one more than its right operand. Walks go down the left operands of a chain in a loop, so
+    // `1 + 2 + 3` has a height of 2, while `(1 + 2) * 3` has a height of 3.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Evaluate the expression at compile time, using only the literals it contains.
+    // Returns None if evaluation fails, e.g. on overflow or a reference to a variable.
+    pub fn evaluate_const(&self) -> Option<value::Value> {
+        evaluator::ASTEvaluator::new().try_evaluate(self).ok()
     }
 
     pub fn number(number: i64) -> Self {
         ASTExpression::new(ASTExpressionKind::Number(ASTNumberExpression { number }))
     }
 
+    pub fn float(number: f64, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Float(ASTFloatExpression { number, token }))
+    }
+
+    pub fn variable(identifier: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Variable(ASTVariableExpression { identifier }))
+    }
+
+    pub fn unary(operator: ASTUnaryOperator, operand: ASTExpression) -> Self {
+        ASTExpression::new(ASTExpressionKind::Unary(ASTUnaryExpression { operator, operand: Box::new(operand) }))
+    }
+
     pub fn binary(operator: ASTBinaryOperator, left: ASTExpression, right: ASTExpression) -> Self {
         ASTExpression::new(ASTExpressionKind::Binary(ASTBinaryExpression {
             left: Box::new(left),
@@ -224,4 +590,119 @@ impl ASTExpression {
             expression: Box::new(expression),
         }))
     }
+
+    pub fn factorial(expression: ASTExpression, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Factorial(ASTFactorialExpression {
+            expression: Box::new(expression),
+            token,
+        }))
+    }
+
+    pub fn absolute(expression: ASTExpression, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Absolute(ASTAbsoluteExpression {
+            expression: Box::new(expression),
+            token,
+        }))
+    }
+}
+
+// Drop a chain like `1 + 2 + 3` one left operand at a time, as the default drop would recurse
+// once per operator and could exhaust the stack on a long one
+impl Drop for ASTExpression {
+    fn drop(&mut self) {
+        let ASTExpressionKind::Binary(expr) = &mut self.kind else {
+            return;
+        };
+        let mut next = expr.take_binary_left();
+        while let Some(mut expression) = next {
+            next = match &mut expression.kind {
+                ASTExpressionKind::Binary(expr) => expr.take_binary_left(),
+                _ => None,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+
+    #[test]
+    fn only_power_associates_right() {
+        use ASTBinaryOperatorKind::*;
+        let kinds = [
+            Plus,
+            Minus,
+            Multiply,
+            Divide,
+            Modulo,
+            Power,
+            Equals,
+            NotEquals,
+            LessThan,
+            LessThanOrEqual,
+            GreaterThan,
+            GreaterThanOrEqual,
+        ];
+        for kind in kinds {
+            let token = Token::new(lexer::TokenKind::Bad, TextSpan::new(0, 1, "?".to_string()));
+            let expected = if kind == Power { Associativity::Right } else { Associativity::Left };
+            assert_eq!(ASTBinaryOperator::new(kind, token).associativity(), expected, "{:?}", kind);
+        }
+    }
+
+    fn parse_expression(input: &str) -> ASTExpression {
+        match Parser::new(Lexer::new(input).tokenize_all()).next_statement().map(|statement| statement.kind) {
+            Some(ASTStatementKind::Expression(expression)) => expression,
+            _ => panic!("expected an expression statement"),
+        }
+    }
+
+    #[test]
+    fn evaluate_const_needs_only_literals() {
+        assert_eq!(parse_expression("2 * 3").evaluate_const(), Some(value::Value::Int(6)));
+        assert_eq!(parse_expression("x * 3").evaluate_const(), None);
+        assert_eq!(parse_expression("1 / 0").evaluate_const(), None);
+    }
+
+    #[test]
+    fn node_at_finds_the_innermost_expression() {
+        let mut ast = Ast::new();
+        ast.add_statement(ASTStatement::expression(parse_expression("1 + 2")));
+        let Some(walk::NodeRef::Expression(number)) = ast.node_at(4) else {
+            panic!("expected an expression at offset 4");
+        };
+        assert!(matches!(number.kind(), ASTExpressionKind::Number(ASTNumberExpression { number: 2 })));
+        let Some(walk::NodeRef::Expression(sum)) = ast.node_at(2) else {
+            panic!("expected an expression at offset 2");
+        };
+        assert!(matches!(sum.kind(), ASTExpressionKind::Binary(_)));
+        assert!(ast.node_at(5).is_none());
+    }
+
+    #[test]
+    fn binary_operator_symbol_comes_from_its_kind() {
+        use ASTBinaryOperatorKind::*;
+        let expected = [
+            (Plus, "+"),
+            (Minus, "-"),
+            (Multiply, "*"),
+            (Divide, "/"),
+            (Modulo, "%"),
+            (Power, "**"),
+            (Equals, "=="),
+            (NotEquals, "!="),
+            (LessThan, "<"),
+            (LessThanOrEqual, "<="),
+            (GreaterThan, ">"),
+            (GreaterThanOrEqual, ">="),
+        ];
+        for (kind, symbol) in expected {
+            // The token's own text is ignored
+            let token = Token::new(lexer::TokenKind::Bad, TextSpan::new(0, 1, "?".to_string()));
+            assert_eq!(ASTBinaryOperator::new(kind, token).symbol(), symbol);
+        }
+    }
 }
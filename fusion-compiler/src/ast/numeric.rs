@@ -0,0 +1,117 @@
+// The arithmetic the evaluator needs from a numeric backend. Operations that can
+// overflow return None when the result doesn't fit the backend.
+pub trait Numeric: Copy + PartialOrd + std::fmt::Debug + std::fmt::Display {
+    // Convert an integer literal into the backend's representation.
+    fn from_i64(value: i64) -> Self;
+
+    // Convert the outcome of a comparison into 1 when it holds and 0 otherwise.
+    fn from_bool(value: bool) -> Self {
+        Self::from_i64(i64::from(value))
+    }
+
+    // Convert a float literal into the backend's representation, or None if it has none.
+    fn from_f64(value: f64) -> Option<Self>;
+
+    fn add(self, other: Self) -> Option<Self>;
+
+    fn sub(self, other: Self) -> Option<Self>;
+
+    fn mul(self, other: Self) -> Option<Self>;
+
+    // Division by a non-zero value.
+    fn div(self, other: Self) -> Option<Self>;
+
+    // The remainder left by `div`.
+    fn rem(self, other: Self) -> Option<Self>;
+
+    // Whether the two values have the same representation, so combining them converts neither.
+    // Backends with a single representation always do.
+    fn same_kind(self, _other: Self) -> bool {
+        true
+    }
+
+    // Whether `div` keeps the whole quotient, rather than truncating a remainder away.
+    fn divides_exactly(self, other: Self) -> bool;
+
+    // Raise to a power, or None if the result overflows the backend or the backend can't
+    // represent a negative power.
+    fn pow(self, exponent: Self) -> Option<Self>;
+
+    // The negated value, or None if it overflows the backend.
+    fn neg(self) -> Option<Self>;
+
+    // Whether the value is a whole number, which backends without fractions always hold.
+    fn is_integral(self) -> bool {
+        true
+    }
+
+    // The name of the value's type, for messages that mention it. Backends without floats only
+    // have integers.
+    fn type_name(self) -> &'static str {
+        "int"
+    }
+
+    // Whether the value is NaN, the result of an operation with no real answer such as
+    // `(-1) ** 0.5`. Backends without floats never produce one.
+    fn is_nan(self) -> bool {
+        false
+    }
+
+    // The factorial of a non-negative whole number, or None if it overflows the backend.
+    fn factorial(self) -> Option<Self>;
+
+    // The absolute value, or None if it overflows the backend.
+    fn abs(self) -> Option<Self>;
+}
+
+// An integer-only backend: `i64` arithmetic, checked for overflow. It has no floats, so float
+// literals fail to evaluate; the default backend is `Value`.
+impl Numeric for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn from_f64(_value: f64) -> Option<Self> {
+        None
+    }
+
+    fn add(self, other: Self) -> Option<Self> {
+        self.checked_add(other)
+    }
+
+    fn sub(self, other: Self) -> Option<Self> {
+        self.checked_sub(other)
+    }
+
+    fn mul(self, other: Self) -> Option<Self> {
+        self.checked_mul(other)
+    }
+
+    fn div(self, other: Self) -> Option<Self> {
+        self.checked_div(other)
+    }
+
+    fn rem(self, other: Self) -> Option<Self> {
+        self.checked_rem(other)
+    }
+
+    fn divides_exactly(self, other: Self) -> bool {
+        self.checked_rem(other).is_none_or(|remainder| remainder == 0)
+    }
+
+    fn pow(self, exponent: Self) -> Option<Self> {
+        u32::try_from(exponent).ok().and_then(|exponent| self.checked_pow(exponent))
+    }
+
+    fn neg(self) -> Option<Self> {
+        self.checked_neg()
+    }
+
+    fn factorial(self) -> Option<Self> {
+        (2..=self).try_fold(1i64, |product, n| product.checked_mul(n))
+    }
+
+    fn abs(self) -> Option<Self> {
+        self.checked_abs()
+    }
+}
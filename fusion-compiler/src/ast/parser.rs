@@ -1,51 +1,333 @@
 // Import necessary modules and types
+use std::fmt;
+
+use crate::ast::lexer::{Lexer, TextSpan, Token};
 use crate::ast::ASTStatement;
-use crate::ast::lexer::{Lexer, Token};
 
-use super::{ASTExpression, ASTBinaryOperator, ASTBinaryOperatorKind};
+use super::diagnostics::bad_token_message;
 use super::lexer::TokenKind;
+use super::{
+    ASTBinaryOperator, ASTBinaryOperatorKind, ASTExpression, ASTExpressionKind, ASTStatementKind, ASTUnaryOperator,
+    ASTUnaryOperatorKind, Associativity, NodeId,
+};
+
+// An error found while parsing, pointing at the offending token
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    // The span of the token where the error was detected
+    pub span: TextSpan,
+    // The span of the enclosing statement, from its first token to the last consumed one
+    pub context_span: Option<TextSpan>,
+    // For errors about a token of the wrong kind, the kind that was expected
+    pub expected: Option<TokenKind>,
+    // For errors about a token of the wrong kind, the kind that was found instead
+    pub found: Option<TokenKind>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span)
+    }
+}
+
+// The default limit on how deeply expressions may nest. Each group or right operand recurses
+// through the half dozen parsing functions, whose frames add up to about 14 KiB in a debug build,
+// so this keeps the deepest accepted input within a thread's default 2 MiB stack. Chains like
+// `1 + 2 + 3` are parsed and walked in a loop and don't count towards it.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
 
 // Define the Parser struct to process tokens
 pub struct Parser {
     tokens: Vec<super::lexer::Token>,
     current: usize,
+    // Index of the first token of the statement being parsed
+    statement_start: usize,
+    errors: Vec<ParseError>,
+    // When set, only these binary operators may appear in the input
+    allowed_operators: Option<Vec<ASTBinaryOperatorKind>>,
+    // Whether a postfix `!` is parsed as factorial
+    postfix_factorial: bool,
+    // Whether `|expr|` is parsed as the absolute value of `expr`
+    pipe_is_abs: bool,
+    // Whether a number directly followed by `(` or a variable multiplies it, e.g. `2(3 + 4)` or `3x`
+    implicit_mul: bool,
+    // How deeply expressions may nest before the parser gives up, counting each group, sign and
+    // right operand
+    max_depth: usize,
+    // How many levels of nesting enclose the expression being parsed
+    depth: usize,
+    // The opening delimiters of the groups currently being parsed, outermost first
+    open_groups: Vec<Token>,
+    // Whether empty input is an error rather than a program with no statements
+    strict: bool,
+    // Whether an expression statement other than the last one is an error, as its value is discarded
+    deny_unused: bool,
+    statement_count: usize,
+    // The `//!` pragmas in the input, and whether each comes before the first statement
+    pragmas: Vec<(Token, bool)>,
+    // Operator precedences set by `//!prec` pragmas, replacing the defaults
+    precedence_overrides: Vec<(ASTBinaryOperatorKind, u8)>,
+    // The id given to the next node numbered
+    next_id: usize,
 }
 
 impl Parser {
     // Create a new Parser instance from a vector of tokens
     pub fn new(tokens: Vec<Token>) -> Self {
-        // Remove whitespace tokens and store non-whitespace tokens in 'tokens' field
+        let first_start = tokens.iter().find(|token| !token.is_trivia()).map(|token| token.span.start);
+        let pragmas = tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Pragma)
+            .map(|token| (token.clone(), first_start.is_none_or(|start| token.span.start < start)))
+            .collect();
+        // Remove trivia tokens and store the remaining tokens in 'tokens' field
         Self {
-            tokens: tokens
-                .iter()
-                .filter(|token| token.kind != TokenKind::Whitespace)
-                .map(|token| token.clone())
-                .collect(),
+            tokens: tokens.into_iter().filter(|token| !token.is_trivia()).collect(),
             current: 0,
+            statement_start: 0,
+            errors: Vec::new(),
+            allowed_operators: None,
+            postfix_factorial: false,
+            pipe_is_abs: false,
+            implicit_mul: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            open_groups: Vec::new(),
+            strict: false,
+            deny_unused: false,
+            statement_count: 0,
+            pragmas,
+            precedence_overrides: Vec::new(),
+            next_id: 0,
         }
     }
 
+    // Restrict the parser to the given binary operators; any other operator is reported as an error
+    pub fn with_allowed_operators(mut self, operators: Vec<ASTBinaryOperatorKind>) -> Self {
+        self.allowed_operators = Some(operators);
+        self
+    }
+
+    // Enable or disable parsing a postfix `!` as factorial, e.g. `5!`
+    pub fn with_postfix_factorial(mut self, enabled: bool) -> Self {
+        self.postfix_factorial = enabled;
+        self
+    }
+
+    // Enable or disable parsing `|expr|` as an absolute value, e.g. `|3 - 5|`
+    pub fn with_pipe_is_abs(mut self, enabled: bool) -> Self {
+        self.pipe_is_abs = enabled;
+        self
+    }
+
+    // Enable or disable implicit multiplication of a number directly followed by `(` or a variable,
+    // e.g. `2(3 + 4)` or `3x`
+    pub fn with_implicit_mul(mut self, enabled: bool) -> Self {
+        self.implicit_mul = enabled;
+        self
+    }
+
+    // Limit how deeply expressions may nest, counting each group, sign and right operand, so `(1 + 2) * 3`
+    // nests 3 deep but a chain like `1 + 2 + 3` only 2, as its left operands are walked in a loop. This
+    // bounds the recursion of the parser and of anything that walks the AST.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Enable or disable strict mode, where the input must hold at least one statement
+    pub fn with_strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    // Enable or disable reporting expression statements whose value is discarded, e.g. the `1 + 2`
+    // in `1 + 2; 3`. Let and print statements, and the last statement, are exempt.
+    pub fn with_deny_unused(mut self, enabled: bool) -> Self {
+        self.deny_unused = enabled;
+        self
+    }
+
+    // Enable or disable `//!` pragmas at the top of the input. The only one is `//!prec <op> <n>`,
+    // which gives a binary operator a precedence from 1 to 9, e.g. `//!prec + 5` makes `+` bind
+    // tighter than `*`. A malformed pragma, or one after the first statement, is an error.
+    // When disabled, pragmas are ordinary comments.
+    pub fn with_pragmas(mut self, enabled: bool) -> Self {
+        self.precedence_overrides.clear();
+        if enabled {
+            for (token, leading) in self.pragmas.clone() {
+                self.apply_pragma(&token, leading);
+            }
+        }
+        self
+    }
+
     // A convenience function to create a new Parser instance from a vector of tokens
     pub fn from_tokens(tokens: Vec<Token>) -> Self {
         Self::new(tokens)
     }
 
-    // Parse the next statement in the token stream
+    // Parse the next statement in the token stream. A statement with an error is recorded and
+    // skipped up to the next `;`, so parsing resumes with the statement after it.
     pub fn next_statement(&mut self) -> Option<ASTStatement> {
-        let token = self.current()?;
-        // If the current token is EOF, return None to signal the end of parsing
-        if token.kind == TokenKind::EOF {
-            return None;
+        loop {
+            // If there are no tokens left to parse, return None to signal the end of parsing
+            if self.is_at_end() {
+                if self.strict && self.statement_count == 0 && self.errors.is_empty() {
+                    let span = self.current()?.span.clone();
+                    self.report("expected an expression, found empty input".to_string(), span);
+                }
+                return None;
+            }
+            // Otherwise, parse the statement and return the result
+            match self.parse_statement() {
+                Some(mut statement) => {
+                    self.statement_count += 1;
+                    self.number_statement(&mut statement);
+                    return Some(statement);
+                }
+                None => self.synchronize(),
+            }
         }
-        // Otherwise, parse the statement and return the result
-        return self.parse_statement();
     }
 
-    // Parse a statement, which is essentially an expression in this simplified example
+    // Skip the rest of a statement that failed to parse, up to and including its `;`
+    fn synchronize(&mut self) {
+        // The failed statement may have consumed its `;` already, as `let ;` does
+        let consumed_any = self.current > self.statement_start;
+        if consumed_any && self.peek(-1).is_some_and(|token| token.kind == TokenKind::Semicolon) {
+            return;
+        }
+        while !self.is_at_end() {
+            if self.consume().is_some_and(|token| token.kind == TokenKind::Semicolon) {
+                return;
+            }
+        }
+    }
+
+    // Whether the parser has reached the EOF token or run past the end of the tokens
+    pub fn is_at_end(&self) -> bool {
+        match self.current() {
+            Some(token) => token.kind == TokenKind::EOF,
+            None => true,
+        }
+    }
+
+    // The number of tokens left to parse, not counting EOF
+    pub fn remaining(&self) -> usize {
+        self.tokens.iter().skip(self.current).filter(|token| token.kind != TokenKind::EOF).count()
+    }
+
+    // The errors reported so far
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    // Parse a statement, which is either a print statement or an expression, followed by a `;` or
+    // the end of input. Semicolons separate statements, so one after the last statement may be
+    // omitted, but anything else after a statement is an error, as in `1 2`.
     fn parse_statement(&mut self) -> Option<ASTStatement> {
-        let token = self.current()?;
-        let expr = self.parse_expression()?; // Parse the expression part of the statement
-        return Some(ASTStatement::expression(expr));
+        self.statement_start = self.current;
+        let statement = if self.current()?.kind == TokenKind::Print {
+            self.parse_print_statement()?
+        } else if self.current()?.kind == TokenKind::Let {
+            self.parse_let_statement()?
+        } else {
+            let expr = self.parse_expression()?; // Parse the expression part of the statement
+            ASTStatement::expression(expr)
+        };
+        let span = self.statement_span();
+        let token = self.current()?.clone();
+        match token.kind {
+            TokenKind::Semicolon => {
+                self.consume(); // Consume the `;` terminating the statement
+            }
+            TokenKind::EOF => {}
+            // A token the lexer couldn't make sense of is reported as such, as it would be anywhere else
+            TokenKind::Bad => {
+                self.report_not_an_expression(token);
+                return None;
+            }
+            _ => {
+                let message = format!("expected `;` or end of input, found {}", Self::describe(&token));
+                self.report_unexpected(message, token.span, TokenKind::Semicolon, token.kind);
+                return None;
+            }
+        }
+        if self.deny_unused && matches!(statement.kind, ASTStatementKind::Expression(_)) && !self.is_at_end() {
+            if let Some(span) = span {
+                self.report("unused result: the value of this expression is discarded".to_string(), span);
+            }
+        }
+        Some(statement)
+    }
+
+    // Parse `let name = expr`, binding the value of the expression to the name
+    fn parse_let_statement(&mut self) -> Option<ASTStatement> {
+        self.consume(); // Consume the `let` keyword
+        let identifier = self.consume()?.clone();
+        if !matches!(identifier.kind, TokenKind::Identifier(_)) {
+            let message = format!("expected an identifier, found {}", Self::describe(&identifier));
+            self.report(message, identifier.span);
+            return None;
+        }
+        self.consume_expected(TokenKind::Equals)?;
+        let initializer = self.parse_expression()?;
+        Some(ASTStatement::let_statement(identifier, initializer))
+    }
+
+    // Parse `print(a, b, ...)` with zero or more comma-separated arguments
+    fn parse_print_statement(&mut self) -> Option<ASTStatement> {
+        self.consume(); // Consume the `print` keyword
+        self.consume_expected(TokenKind::LeftParen)?;
+        let mut arguments = Vec::new();
+        if self.current()?.kind != TokenKind::RightParen {
+            arguments.push(self.parse_expression()?);
+            while self.current()?.kind == TokenKind::Comma {
+                self.consume(); // Consume the comma separating the arguments
+                arguments.push(self.parse_expression()?);
+            }
+        }
+        self.consume_expected(TokenKind::RightParen)?;
+        Some(ASTStatement::print(arguments))
+    }
+
+    // Consume the current token if it has the given kind, otherwise return an error saying what was
+    // expected and what was found instead. The error is not recorded, so callers may recover from it.
+    pub fn expect(&mut self, kind: TokenKind) -> Result<&Token, Box<ParseError>> {
+        let token = match self.consume().cloned() {
+            Some(token) => token,
+            // Past the end of the tokens, report the position of the last one, normally EOF
+            None => self
+                .tokens
+                .last()
+                .cloned()
+                .unwrap_or_else(|| Token::new(TokenKind::EOF, TextSpan::new(0, 0, "\0".to_string()))),
+        };
+        if token.kind != kind {
+            let message = format!("expected {}, found {}", Self::describe_kind(&kind), Self::describe(&token));
+            let context_span = self.statement_span();
+            return Err(Box::new(ParseError {
+                message,
+                span: token.span,
+                context_span,
+                expected: Some(kind),
+                found: Some(token.kind),
+            }));
+        }
+        Ok(&self.tokens[self.current - 1])
+    }
+
+    // Like `expect`, but records the error instead of returning it
+    fn consume_expected(&mut self, kind: TokenKind) -> Option<()> {
+        match self.expect(kind) {
+            Ok(_) => Some(()),
+            Err(error) => {
+                self.errors.push(*error);
+                None
+            }
+        }
     }
 
     // Parse an expression, which may include binary operations
@@ -53,61 +335,493 @@ impl Parser {
         self.parse_binary_expression(0) // Start with the lowest precedence (0) for binary operators
     }
 
-    // Parse a binary expression with a given precedence level
+    // Parse a binary expression with a given precedence level, one level of nesting deeper
     fn parse_binary_expression(&mut self, precedence: u8) -> Option<ASTExpression> {
-        let mut left = self.parse_primary_expression()?; // Parse the left-hand side of the binary expression
+        self.enter_nested()?;
+        let expr = self.parse_binary_operations(precedence);
+        self.depth -= 1;
+        expr
+    }
+
+    // Parse an operand followed by any binary operators of at least the given precedence
+    fn parse_binary_operations(&mut self, precedence: u8) -> Option<ASTExpression> {
+        let start = self.current;
+        let mut left = self.parse_unary_expression()?; // Parse the left-hand side of the binary expression
 
         // Keep parsing binary operators and their right-hand operands until the precedence is lower
         while let Some(operator) = self.parse_binary_operator() {
-            self.consume(); // Consume the operator token
-            let operator_precedence = operator.precedence(); // Get the precedence of the operator
+            let operator_precedence = self.precedence(operator.kind); // Get the precedence of the operator
             if operator_precedence < precedence {
+                // Leave the operator for the caller parsing at the lower precedence level
                 break;
             }
-            let right = self.parse_binary_expression(operator_precedence)?; // Parse the right-hand side
-            left = ASTExpression::binary(operator, left, right); // Create a binary expression node
+            let operator_index = self.current;
+            if !self.is_implicit_mul(&operator) {
+                self.consume(); // Consume the operator token now that it belongs to this level
+            }
+            if !self.is_allowed(&operator.kind) {
+                self.report_operator(&operator, |symbol| format!("operator `{}` is not allowed", symbol));
+                return None;
+            }
+            if self.is_missing_operand() {
+                // Report an operator with nothing after it, pointing at the operator itself
+                self.report_operator(&operator, |symbol| format!("expected expression after `{}`", symbol));
+                return None;
+            }
+            // Left-associative operators stop the right-hand side at their own precedence,
+            // right-associative ones let it absorb further operators of the same precedence
+            let right_precedence = match operator.associativity() {
+                Associativity::Left => operator_precedence + 1,
+                Associativity::Right => operator_precedence,
+            };
+            let right = self.parse_binary_expression(right_precedence)?; // Parse the right-hand side
+            left = self.spanned(ASTExpression::binary(operator, left, right), start); // Create a binary expression node
+            self.check_height(&left, operator_index)?;
         }
 
-        return Some(left);
+        Some(left)
+    }
+
+    // The precedence of an operator, as set by a `//!prec` pragma or else its default
+    fn precedence(&self, operator: ASTBinaryOperatorKind) -> u8 {
+        self.precedence_overrides
+            .iter()
+            .rev()
+            .find(|(kind, _)| *kind == operator)
+            .map_or_else(|| operator.precedence(), |(_, precedence)| *precedence)
+    }
+
+    // Read a `//!` pragma, recording the override it sets or reporting why it can't be applied
+    fn apply_pragma(&mut self, token: &Token, leading: bool) {
+        let span = token.span.clone();
+        if !leading {
+            self.report("pragmas must come before the first statement".to_string(), span);
+            return;
+        }
+        let directive = token.span.literal.trim_start_matches("//!");
+        let words: Vec<&str> = directive.split_whitespace().collect();
+        let [name, symbol, precedence] = words[..] else {
+            let message = format!("invalid pragma `{}`, expected `//!prec <operator> <precedence>`", directive.trim());
+            self.report(message, span);
+            return;
+        };
+        if name != "prec" {
+            self.report(format!("unknown pragma `{}`", name), span);
+            return;
+        }
+        let mut operator_tokens = Lexer::new(symbol).tokenize_all().into_iter();
+        let kind = match (operator_tokens.next(), operator_tokens.next()) {
+            (Some(token), Some(end)) if end.kind == TokenKind::EOF => Self::binary_operator_kind(&token.kind),
+            _ => None,
+        };
+        let Some(kind) = kind else {
+            self.report(format!("`{}` is not a binary operator", symbol), span);
+            return;
+        };
+        match precedence.parse() {
+            Ok(precedence @ 1..=9) => self.precedence_overrides.push((kind, precedence)),
+            _ => self.report(format!("invalid precedence `{}`, expected a number from 1 to 9", precedence), span),
+        }
+    }
+
+    // Check whether an operator passes the configured whitelist, if any
+    fn is_allowed(&self, kind: &ASTBinaryOperatorKind) -> bool {
+        match &self.allowed_operators {
+            Some(operators) => operators.contains(kind),
+            None => true,
+        }
+    }
+
+    // Check whether the statement ends, or an operator that can't start an operand follows, where a
+    // right operand is expected. A sign may start one, as in `2 - -3`.
+    fn is_missing_operand(&mut self) -> bool {
+        match self.current() {
+            Some(token) => {
+                let is_sign = matches!(token.kind, TokenKind::Plus | TokenKind::Minus);
+                let ends_statement = matches!(token.kind, TokenKind::EOF | TokenKind::Semicolon);
+                let is_operator = self.parse_binary_operator().is_some_and(|operator| !self.is_implicit_mul(&operator));
+                ends_statement || (is_operator && !is_sign)
+            }
+            None => true,
+        }
     }
 
     // Parse a binary operator
     fn parse_binary_operator(&mut self) -> Option<ASTBinaryOperator> {
         let token = self.current()?;
         let kind = match token.kind {
+            TokenKind::LeftParen | TokenKind::Identifier(_) if self.follows_number_directly(token) => {
+                Some(ASTBinaryOperatorKind::Multiply)
+            }
+            _ => Self::binary_operator_kind(&token.kind),
+        };
+
+        // If the token is a valid binary operator, create and return the corresponding operator node
+        kind.map(|kind| ASTBinaryOperator::new(kind, token.clone()))
+    }
+
+    // Map a token kind to the binary operator it spells, if any
+    fn binary_operator_kind(kind: &TokenKind) -> Option<ASTBinaryOperatorKind> {
+        match kind {
             // Map token kinds to corresponding binary operator kinds
             TokenKind::Plus => Some(ASTBinaryOperatorKind::Plus),
             TokenKind::Minus => Some(ASTBinaryOperatorKind::Minus),
             TokenKind::Asterisk => Some(ASTBinaryOperatorKind::Multiply),
+            TokenKind::DoubleAsterisk => Some(ASTBinaryOperatorKind::Power),
             TokenKind::Slash => Some(ASTBinaryOperatorKind::Divide),
+            TokenKind::Percent => Some(ASTBinaryOperatorKind::Modulo),
+            TokenKind::EqualsEquals => Some(ASTBinaryOperatorKind::Equals),
+            TokenKind::BangEquals => Some(ASTBinaryOperatorKind::NotEquals),
+            TokenKind::LessThan => Some(ASTBinaryOperatorKind::LessThan),
+            TokenKind::LessThanEquals => Some(ASTBinaryOperatorKind::LessThanOrEqual),
+            TokenKind::GreaterThan => Some(ASTBinaryOperatorKind::GreaterThan),
+            TokenKind::GreaterThanEquals => Some(ASTBinaryOperatorKind::GreaterThanOrEqual),
             _ => None,
+        }
+    }
+
+    // Check whether the token comes right after a number with no space between them, as the `(`
+    // in `2(3 + 4)` or the `x` in `3x` does. Only applies when implicit multiplication is enabled.
+    fn follows_number_directly(&self, token: &Token) -> bool {
+        match self.peek(-1) {
+            Some(previous) => {
+                self.implicit_mul
+                    && matches!(previous.kind, TokenKind::Number(_) | TokenKind::Float(_))
+                    && previous.span.end == token.span.start
+            }
+            None => false,
+        }
+    }
+
+    // Check whether an operator is implied rather than written, in which case its token starts
+    // the right operand and must not be consumed
+    fn is_implicit_mul(&self, operator: &ASTBinaryOperator) -> bool {
+        matches!(operator.token.kind, TokenKind::LeftParen | TokenKind::Identifier(_))
+    }
+
+    // Parse any prefix signs and the operand they apply to. Signs bind tighter than every binary
+    // operator except `**`, so `-2 * 3` is `(-2) * 3` but `-2 ** 2` is `-(2 ** 2)`, which is -4.
+    // Signs may repeat, as in `- -5`, and are read in a loop so a long run of them can't exhaust the stack.
+    fn parse_unary_expression(&mut self) -> Option<ASTExpression> {
+        let start = self.current;
+        let mut signs = Vec::new();
+        while let Some(kind) = self.current().and_then(|token| Self::unary_operator_kind(&token.kind)) {
+            let token = self.consume()?.clone(); // Consume the sign
+            if self.is_at_end() {
+                self.report_missing_operand(token);
+                return None;
+            }
+            signs.push((kind, token));
+        }
+        let mut expr = if signs.is_empty() {
+            self.parse_postfix_expression()?
+        } else {
+            self.parse_binary_expression(self.precedence(ASTBinaryOperatorKind::Power))?
         };
+        // Apply the signs innermost first; each one's span starts at its own token
+        for (offset, (kind, token)) in signs.into_iter().enumerate().rev() {
+            expr = self.spanned(ASTExpression::unary(ASTUnaryOperator::new(kind, token), expr), start + offset);
+            self.check_height(&expr, start + offset)?;
+        }
+        Some(expr)
+    }
 
-        // If the token is a valid binary operator, create and return the corresponding operator node
-        return kind.map(|kind| ASTBinaryOperator::new(kind, token.clone()));
+    // Map a token kind to the prefix sign it spells, if any
+    fn unary_operator_kind(kind: &TokenKind) -> Option<ASTUnaryOperatorKind> {
+        match kind {
+            TokenKind::Minus => Some(ASTUnaryOperatorKind::Negate),
+            TokenKind::Plus => Some(ASTUnaryOperatorKind::Identity),
+            _ => None,
+        }
     }
 
-    // Parse a primary expression, which can be a number or a parenthesized expression
+    // Parse a primary expression followed by any postfix operators, which bind tighter than binary ones
+    fn parse_postfix_expression(&mut self) -> Option<ASTExpression> {
+        let start = self.current;
+        let mut expr = self.parse_primary_expression()?;
+        expr = self.spanned(expr, start);
+        self.check_height(&expr, start)?; // A group nests one deeper than its contents
+        while self.postfix_factorial && self.current()?.kind == TokenKind::Bang {
+            let bang = self.current;
+            let token = self.consume()?.clone(); // Consume the `!` token
+            expr = self.spanned(ASTExpression::factorial(expr, token), start);
+            self.check_height(&expr, bang)?;
+        }
+        Some(expr)
+    }
+
+    // Parse a primary expression, which can be a number, a parenthesized expression or an absolute value
     fn parse_primary_expression(&mut self) -> Option<ASTExpression> {
-        let token = self.consume()?; // Consume the current token
+        let token = self.consume()?.clone(); // Consume the current token
         match token.kind {
             TokenKind::Number(number) => {
-                return Some(ASTExpression::number(number)); // Create a number node
-            },
+                Some(ASTExpression::number(number)) // Create a number node
+            }
+            TokenKind::Float(number) => {
+                Some(ASTExpression::float(number, token)) // Create a float node
+            }
+            TokenKind::Identifier(_) => {
+                Some(ASTExpression::variable(token)) // Create a variable reference node
+            }
             TokenKind::LeftParen => {
-                let expr = self.parse_expression()?; // Parse the expression inside the parentheses
-                let token = self.consume()?;
-                if token.kind != TokenKind::RightParen {
-                    panic!("Expected right paren");
-                }
+                // Parse the expression inside the parentheses
+                let expr = self.parse_group(token, TokenKind::RightParen)?;
                 Some(ASTExpression::paranthesized(expr)) // Create a parentheses expression node
-            },
-            _  => {
-                None // Return None for unsupported primary expressions
+            }
+            TokenKind::LeftBracket => {
+                // Brackets group an expression just like parentheses do
+                let expr = self.parse_group(token, TokenKind::RightBracket)?;
+                Some(ASTExpression::paranthesized(expr))
+            }
+            TokenKind::Pipe if self.pipe_is_abs => {
+                // Parse the expression between the bars
+                let expr = self.parse_group(token.clone(), TokenKind::Pipe)?;
+                Some(ASTExpression::absolute(expr, token)) // Create an absolute value node
+            }
+            _ => {
+                // Report unsupported primary expressions and return None
+                self.report_not_an_expression(token);
+                None
             }
         }
     }
 
+    // Report a token that can't start an expression. The reports for the recursive parsing
+    // functions live in functions of their own, keeping the formatting off their stack frames.
+    fn report_not_an_expression(&mut self, token: Token) {
+        let message = if self.is_lone_operator(&token) {
+            format!("expected an expression but found only operator `{}`", token.span.literal)
+        } else {
+            let describe = || format!("expected expression, found {}", Self::describe(&token));
+            bad_token_message(&token).unwrap_or_else(describe)
+        };
+        self.report(message, token.span);
+    }
+
+    // Report a sign with nothing after it, pointing at the sign itself
+    fn report_missing_operand(&mut self, sign: Token) {
+        let message = if self.is_lone_operator(&sign) {
+            format!("expected an expression but found only operator `{}`", sign.span.literal)
+        } else {
+            format!("expected expression after `{}`", sign.span.literal)
+        };
+        self.report(message, sign.span);
+    }
+
+    // Report an error at a binary operator, with a message built from its symbol
+    fn report_operator(&mut self, operator: &ASTBinaryOperator, message: impl FnOnce(&str) -> String) {
+        let message = message(operator.symbol());
+        self.report(message, operator.token.span.clone());
+    }
+
+    // Check whether the just-consumed token is an operator making up the whole statement
+    fn is_lone_operator(&self, token: &Token) -> bool {
+        token.kind.is_operator() && self.statement_start + 1 == self.current && self.is_at_end()
+    }
+
+    // Parse the expression following an opening delimiter and its closing delimiter
+    fn parse_group(&mut self, opener: Token, closing: TokenKind) -> Option<ASTExpression> {
+        self.open_groups.push(opener);
+        let expr = self.parse_expression().and_then(|expr| {
+            self.consume_closing(closing)?;
+            Some(expr)
+        });
+        self.open_groups.pop();
+        expr
+    }
+
+    // Count one more level of nesting, reporting an error at the current token past the limit
+    fn enter_nested(&mut self) -> Option<()> {
+        if self.depth >= self.max_depth {
+            let span = self.current()?.span.clone();
+            self.report_too_deep(span);
+            return None;
+        }
+        self.depth += 1;
+        Some(())
+    }
+
+    // Report an expression nesting deeper than the limit, such as a long chain of operators,
+    // pointing at the token at `token_index` that built it
+    fn check_height(&mut self, expression: &ASTExpression, token_index: usize) -> Option<()> {
+        if expression.height() > self.max_depth {
+            self.report_too_deep(self.tokens[token_index].span.clone());
+            return None;
+        }
+        Some(())
+    }
+
+    fn report_too_deep(&mut self, span: TextSpan) {
+        let message = format!("expression nested too deeply (limit is {})", self.max_depth);
+        self.report(message, span);
+    }
+
+    // Consume the closing delimiter of the innermost group. Running out of input reports the
+    // outermost group still open, which is where the missing delimiter most likely belongs.
+    fn consume_closing(&mut self, closing: TokenKind) -> Option<()> {
+        if !self.is_at_end() {
+            let token = self.current()?.clone();
+            let is_closing = matches!(token.kind, TokenKind::RightParen | TokenKind::RightBracket);
+            if is_closing && token.kind != closing {
+                // A closing delimiter of the wrong kind, as in `(1 + 2]`
+                self.consume();
+                let message = format!(
+                    "mismatched closing delimiter: expected {}, found {}",
+                    Self::describe_kind(&closing),
+                    Self::describe(&token)
+                );
+                self.report_unexpected(message, token.span, closing, token.kind);
+                return None;
+            }
+            return self.consume_expected(closing);
+        }
+        let outermost = self.open_groups.first()?.clone();
+        let mut message = format!("unclosed `{}`", outermost.span.literal);
+        if self.open_groups.len() > 1 {
+            message.push_str(&format!(" ({} groups left open)", self.open_groups.len()));
+        }
+        self.report_unexpected(message, outermost.span, closing, TokenKind::EOF);
+        None
+    }
+
+    // Record an error, attaching the span of the statement parsed so far as context
+    fn report(&mut self, message: String, span: TextSpan) {
+        let context_span = self.statement_span();
+        self.errors.push(ParseError { message, span, context_span, expected: None, found: None });
+    }
+
+    // Record an error about a token of the wrong kind, noting what was expected and what was found
+    fn report_unexpected(&mut self, message: String, span: TextSpan, expected: TokenKind, found: TokenKind) {
+        let context_span = self.statement_span();
+        self.errors.push(ParseError {
+            message,
+            span,
+            context_span,
+            expected: Some(expected),
+            found: Some(found),
+        });
+    }
+
+    // The span from the first token of the current statement to the last consumed token
+    fn statement_span(&self) -> Option<TextSpan> {
+        self.span_from(self.statement_start)
+    }
+
+    // Give an expression the span from the token at index `start` to the last consumed token
+    fn spanned(&self, mut expression: ASTExpression, start: usize) -> ASTExpression {
+        expression.span = self.span_from(start);
+        expression
+    }
+
+    // The span from the token at index `start` to the last consumed token. Only its position is
+    // recorded: copying the text into every expression's span would take memory quadratic in the
+    // length of a chain like `1 + 1 + ...`, so the literal is left empty.
+    fn span_from(&self, start: usize) -> Option<TextSpan> {
+        let first = self.tokens.get(start)?;
+        let last = self.tokens[..self.current.min(self.tokens.len())]
+            .iter()
+            .rev()
+            .find(|token| token.kind != TokenKind::EOF)?;
+        if last.span.end < first.span.start {
+            return None;
+        }
+        let span = TextSpan::new(first.span.start, last.span.end, String::new());
+        Some(span.with_position(first.span.line, first.span.column))
+    }
+
+    // Give the statement and each of its expressions the next ids, in pre-order
+    fn number_statement(&mut self, statement: &mut ASTStatement) {
+        statement.id = self.take_id();
+        match &mut statement.kind {
+            ASTStatementKind::Expression(expr) => self.number_expression(expr),
+            ASTStatementKind::Let(statement) => self.number_expression(&mut statement.initializer),
+            ASTStatementKind::Print(print) => {
+                for argument in &mut print.arguments {
+                    self.number_expression(argument);
+                }
+            }
+        }
+    }
+
+    // Give the expression and its subexpressions the next ids, in pre-order. The left operands of a
+    // chain like `1 + 2 + 3` are numbered in a loop, and their right operands afterwards, innermost first.
+    fn number_expression(&mut self, expression: &mut ASTExpression) {
+        let mut right_operands = Vec::new();
+        let mut expression = expression;
+        loop {
+            expression.id = self.take_id();
+            match &mut expression.kind {
+                ASTExpressionKind::Number(_) | ASTExpressionKind::Float(_) | ASTExpressionKind::Variable(_) => {}
+                ASTExpressionKind::Unary(expr) => self.number_expression(&mut expr.operand),
+                ASTExpressionKind::Binary(expr) => {
+                    right_operands.push(&mut *expr.right);
+                    expression = &mut expr.left;
+                    continue;
+                }
+                ASTExpressionKind::Parenthesized(expr) => self.number_expression(&mut expr.expression),
+                ASTExpressionKind::Factorial(expr) => self.number_expression(&mut expr.expression),
+                ASTExpressionKind::Absolute(expr) => self.number_expression(&mut expr.expression),
+            }
+            break;
+        }
+        for operand in right_operands.into_iter().rev() {
+            self.number_expression(operand);
+        }
+    }
+
+    // Hand out the next unused id
+    fn take_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    // Describe a token for use in error messages
+    fn describe(token: &Token) -> String {
+        match token.kind {
+            TokenKind::EOF => "end of input".to_string(),
+            _ => format!("`{}`", token.span.literal),
+        }
+    }
+
+    // Describe the kind of token the parser expected, for use in error messages
+    fn describe_kind(kind: &TokenKind) -> String {
+        let symbol = match kind {
+            TokenKind::Number(_) => return "a number".to_string(),
+            TokenKind::Float(_) => return "a float".to_string(),
+            TokenKind::Identifier(_) => return "an identifier".to_string(),
+            TokenKind::Whitespace => return "whitespace".to_string(),
+            TokenKind::Comment => return "a comment".to_string(),
+            TokenKind::Pragma => return "a pragma".to_string(),
+            TokenKind::EOF => return "end of input".to_string(),
+            TokenKind::Bad => return "an unrecognized token".to_string(),
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Asterisk => "*",
+            TokenKind::DoubleAsterisk => "**",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Semicolon => ";",
+            TokenKind::Bang => "!",
+            TokenKind::Pipe => "|",
+            TokenKind::Equals => "=",
+            TokenKind::EqualsEquals => "==",
+            TokenKind::BangEquals => "!=",
+            TokenKind::LessThan => "<",
+            TokenKind::LessThanEquals => "<=",
+            TokenKind::GreaterThan => ">",
+            TokenKind::GreaterThanEquals => ">=",
+            TokenKind::Print => "print",
+            TokenKind::Let => "let",
+        };
+        format!("`{}`", symbol)
+    }
+
     // Peek at a token with a given offset from the current position
     fn peek(&self, offset: isize) -> Option<&Token> {
         self.tokens.get((self.current as isize + offset) as usize)
@@ -122,6 +836,271 @@ impl Parser {
     fn consume(&mut self) -> Option<&Token> {
         self.current += 1;
         let token = self.peek(-1)?;
-        return Some(token);
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::value::Value;
+
+    // Parse every statement of the input, returning how many parsed and the errors reported.
+    fn parse_statements(input: &str) -> (usize, Vec<ParseError>) {
+        let mut parser = Parser::new(Lexer::new(input).tokenize_all());
+        let mut count = 0;
+        while parser.next_statement().is_some() {
+            count += 1;
+        }
+        (count, parser.errors().to_vec())
+    }
+
+    #[test]
+    fn error_carries_the_statement_as_context() {
+        let (_, errors) = parse_statements("1; (1 + )");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected expression, found `)`");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (8, 9));
+        let context = errors[0].context_span.as_ref().expect("a context span");
+        assert_eq!((context.start, context.end), (3, 9));
+    }
+
+    #[test]
+    fn trailing_operator_is_reported_at_the_operator() {
+        let (_, errors) = parse_statements("1 + 2 *");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected expression after `*`");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (6, 7));
+
+        let (_, errors) = parse_statements("1 * 2 +");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected expression after `+`");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (6, 7));
+    }
+
+    #[test]
+    fn is_at_end_flips_after_the_last_statement() {
+        let mut parser = Parser::new(Lexer::new("1 + 2; 3").tokenize_all());
+        assert!(!parser.is_at_end());
+        assert_eq!(parser.remaining(), 5);
+        parser.next_statement();
+        assert!(!parser.is_at_end());
+        assert_eq!(parser.remaining(), 1);
+        parser.next_statement();
+        assert!(parser.is_at_end());
+        assert_eq!(parser.remaining(), 0);
+        assert!(parser.next_statement().is_none());
+        assert!(Parser::new(Lexer::new("  ").tokenize_all()).is_at_end());
+    }
+
+    #[test]
+    fn lone_operator_is_reported_as_such() {
+        for operator in ["+", "-", "*", "/", "%", "**", "==", "!=", "<", "<=", ">", ">="] {
+            let (_, errors) = parse_statements(&format!(" {} ", operator));
+            assert_eq!(errors.len(), 1, "{}", operator);
+            assert_eq!(errors[0].message, format!("expected an expression but found only operator `{}`", operator));
+            assert_eq!((errors[0].span.start, errors[0].span.end), (1, 1 + operator.len()));
+        }
+        let (_, errors) = parse_statements("1 + *");
+        assert_eq!(errors[0].message, "expected expression after `+`");
+    }
+
+    #[test]
+    fn unclosed_groups_point_at_the_outermost_opener() {
+        let (_, errors) = parse_statements("((1 + 2");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unclosed `(` (2 groups left open)");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (0, 1));
+
+        let (_, errors) = parse_statements("1 + ([2 * 3]");
+        assert_eq!(errors[0].message, "unclosed `(`");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (4, 5));
+    }
+
+    fn parse_expression(input: &str) -> ASTExpression {
+        let mut parser = Parser::new(Lexer::new(input).tokenize_all());
+        let statement = parser.next_statement().expect("the input parses");
+        assert!(parser.errors().is_empty(), "unexpected errors: {:?}", parser.errors());
+        match statement.kind {
+            ASTStatementKind::Expression(expression) => expression,
+            _ => panic!("expected an expression statement"),
+        }
+    }
+
+    // Write the expression as an S-expression, e.g. `(+ 2 (* 3 4))` for `2 + 3 * 4`, to compare shapes.
+    fn shape(expression: &ASTExpression) -> String {
+        match expression.kind() {
+            ASTExpressionKind::Number(number) => number.number.to_string(),
+            ASTExpressionKind::Float(float) => float.token.span.literal.clone(),
+            ASTExpressionKind::Variable(variable) => variable.name().to_string(),
+            ASTExpressionKind::Unary(expr) => {
+                let sign = match expr.operator.kind {
+                    ASTUnaryOperatorKind::Negate => "-",
+                    ASTUnaryOperatorKind::Identity => "+",
+                };
+                format!("({} {})", sign, shape(&expr.operand))
+            }
+            ASTExpressionKind::Binary(expr) => {
+                format!("({} {} {})", expr.operator.symbol(), shape(&expr.left), shape(&expr.right))
+            }
+            ASTExpressionKind::Parenthesized(expr) => format!("(group {})", shape(&expr.expression)),
+            ASTExpressionKind::Factorial(expr) => format!("(! {})", shape(&expr.expression)),
+            ASTExpressionKind::Absolute(expr) => format!("(abs {})", shape(&expr.expression)),
+        }
+    }
+
+    #[test]
+    fn prec_pragma_changes_how_operators_nest() {
+        let parse = |pragmas| {
+            let tokens = Lexer::new("//!prec + 5\n1 + 2 * 3").tokenize_all();
+            let mut parser = Parser::new(tokens).with_pragmas(pragmas);
+            let statement = parser.next_statement().expect("the input parses");
+            assert!(parser.errors().is_empty(), "unexpected errors: {:?}", parser.errors());
+            match statement.kind {
+                ASTStatementKind::Expression(expression) => expression,
+                _ => panic!("expected an expression statement"),
+            }
+        };
+        let with_pragma = parse(true);
+        assert_eq!(shape(&with_pragma), "(* (+ 1 2) 3)");
+        assert_eq!(with_pragma.evaluate_const(), Some(Value::Int(9)));
+        let without_pragma = parse(false);
+        assert_eq!(shape(&without_pragma), "(+ 1 (* 2 3))");
+        assert_eq!(without_pragma.evaluate_const(), Some(Value::Int(7)));
+    }
+
+    #[test]
+    fn binary_operators_nest_by_precedence() {
+        assert_eq!(shape(&parse_expression("2 + 3 * 4")), "(+ 2 (* 3 4))");
+        assert_eq!(shape(&parse_expression("2 * 3 + 4")), "(+ (* 2 3) 4)");
+        assert_eq!(shape(&parse_expression("1 - 2 - 3")), "(- (- 1 2) 3)");
+        assert_eq!(shape(&parse_expression("2 + 3 * 4 + 5")), "(+ (+ 2 (* 3 4)) 5)");
+    }
+
+    #[test]
+    fn expect_reports_the_expected_and_found_kinds() {
+        let mut parser = Parser::new(Lexer::new("( 1").tokenize_all());
+        assert_eq!(parser.expect(TokenKind::LeftParen).map(|token| token.kind.clone()), Ok(TokenKind::LeftParen));
+        let error = parser.expect(TokenKind::RightParen).unwrap_err();
+        assert_eq!(error.message, "expected `)`, found `1`");
+        assert_eq!((error.span.start, error.span.end), (2, 3));
+        assert_eq!(error.expected, Some(TokenKind::RightParen));
+        assert_eq!(error.found, Some(TokenKind::Number(1)));
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn node_ids_are_unique_and_in_pre_order() {
+        // The statement takes id 0, then `+`, `1`, `*`, `2` and `3` follow in pre-order
+        let sum = parse_expression("1 + 2 * 3");
+        let ASTExpressionKind::Binary(add) = sum.kind() else { panic!("expected an addition") };
+        let ASTExpressionKind::Binary(mul) = add.right.kind() else { panic!("expected a multiplication") };
+        let ids = [sum.id(), add.left.id(), add.right.id(), mul.left.id(), mul.right.id()];
+        assert_eq!(ids, [1, 2, 3, 4, 5].map(NodeId));
+    }
+
+    #[test]
+    fn missing_right_paren_is_one_structured_error() {
+        let (count, errors) = parse_statements("(3 + 4");
+        assert_eq!(count, 0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].expected, Some(TokenKind::RightParen));
+        assert_eq!(errors[0].found, Some(TokenKind::EOF));
+        // The error points at the unclosed `(`, and its context runs to the end of input
+        assert_eq!((errors[0].span.start(), errors[0].span.end()), (0, 1));
+        let context = errors[0].context_span.as_ref().expect("the error has a context span");
+        assert_eq!((context.start(), context.end()), (0, 6));
+    }
+
+    #[test]
+    fn mismatched_closing_delimiter_is_reported_at_the_closer() {
+        let (_, errors) = parse_statements("(1 + 2]");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "mismatched closing delimiter: expected `)`, found `]`");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (6, 7));
+
+        let (_, errors) = parse_statements("[1 + 2)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "mismatched closing delimiter: expected `]`, found `)`");
+        assert_eq!(errors[0].expected, Some(TokenKind::RightBracket));
+        assert_eq!(errors[0].found, Some(TokenKind::RightParen));
+    }
+
+    #[test]
+    fn expression_span_covers_the_whole_input() {
+        let expression = parse_expression("(1 + 2) * 3");
+        let span = expression.span().expect("parsed expressions have a span");
+        assert_eq!((span.start(), span.end()), (0, 11));
+        assert_eq!((span.line(), span.column()), (1, 1));
+    }
+
+    #[test]
+    fn semicolons_separate_statements() {
+        assert_eq!(parse_statements("1;2;3"), (3, Vec::new()));
+        assert_eq!(parse_statements("1;2;3;"), (3, Vec::new()));
+    }
+
+    #[test]
+    fn statement_without_a_separator_is_an_error() {
+        for input in ["1 2", "1 + 2 3 * 4"] {
+            let (_, errors) = parse_statements(input);
+            assert_eq!(errors.len(), 1, "{}", input);
+            assert_eq!(errors[0].expected, Some(TokenKind::Semicolon), "{}", input);
+            assert!(errors[0].message.starts_with("expected `;` or end of input"), "{}", errors[0].message);
+        }
+    }
+
+    #[test]
+    fn signs_may_repeat() {
+        assert_eq!(shape(&parse_expression("- -5")), "(- (- 5))");
+        assert_eq!(shape(&parse_expression("2 - -3")), "(- 2 (- 3))");
+        assert_eq!(shape(&parse_expression("-2 * 3")), "(* (- 2) 3)");
+    }
+
+    #[test]
+    fn each_sign_spans_from_itself_to_the_operand() {
+        let expression = parse_expression("- +5");
+        assert_eq!(expression.span().map(|span| (span.start(), span.end())), Some((0, 4)));
+        let ASTExpressionKind::Unary(outer) = expression.kind() else { panic!("expected a sign") };
+        assert_eq!(outer.operand.span().map(|span| (span.start(), span.end())), Some((2, 4)));
+    }
+
+    #[test]
+    fn long_run_of_signs_is_rejected_without_recursing() {
+        let input = "-".repeat(100_000) + "1";
+        let mut parser = Parser::new(Lexer::new(&input).tokenize_all());
+        assert!(parser.next_statement().is_none());
+        assert_eq!(parser.errors().len(), 1);
+        assert!(parser.errors()[0].message.starts_with("expression nested too deeply"));
+    }
+
+    #[test]
+    fn sign_binds_looser_than_power() {
+        assert_eq!(shape(&parse_expression("-2 ** 2")), "(- (** 2 2))");
+        assert_eq!(shape(&parse_expression("-2 ** 2 * 3")), "(* (- (** 2 2)) 3)");
+        assert_eq!(shape(&parse_expression("(-2) ** 2")), "(** (group (- 2)) 2)");
+        assert_eq!(shape(&parse_expression("2 ** -1")), "(** 2 (- 1))");
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(shape(&parse_expression("2 ** 3 ** 2")), "(** 2 (** 3 2))");
+    }
+
+    #[test]
+    fn long_power_chain_is_rejected_without_overflowing_the_stack() {
+        let input = "1".to_string() + &"**1".repeat(100_000);
+        let mut parser = Parser::new(Lexer::new(&input).tokenize_all());
+        assert!(parser.next_statement().is_none());
+        assert_eq!(parser.errors().len(), 1);
+        assert!(parser.errors()[0].message.starts_with("expression nested too deeply"));
+    }
+
+    #[test]
+    fn bad_token_after_a_statement_is_reported_as_such() {
+        let (_, errors) = parse_statements("1 @");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unexpected character '@' (U+0040)");
     }
 }
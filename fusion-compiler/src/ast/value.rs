@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::numeric::Numeric;
+
+// A runtime value: an integer or a float. Arithmetic mixing the two promotes the integer to a
+// float, and equality and ordering compare numerically, so `Int(1) == Float(1.0)`.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+impl Value {
+    // The name of the value's type, `int` or `float`, for messages that mention it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+        }
+    }
+
+    // The value as a float, converting an integer.
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(value) => value as f64,
+            Value::Float(value) => value,
+        }
+    }
+
+    // Apply an operation to two values. Two integers use the checked integer operation; otherwise
+    // both are promoted to floats. Either way, None means the result overflowed.
+    fn promote(
+        self,
+        other: Self,
+        int_op: impl Fn(i64, i64) -> Option<i64>,
+        float_op: impl Fn(f64, f64) -> f64,
+    ) -> Option<Self> {
+        match (self, other) {
+            (Value::Int(left), Value::Int(right)) => int_op(left, right).map(Value::Int),
+            (left, right) => Self::finite(float_op(left.as_f64(), right.as_f64())),
+        }
+    }
+
+    // Treat a float result that overflowed to infinity as an overflow. NaN is kept, for the
+    // evaluator to report as an invalid operation.
+    fn finite(value: f64) -> Option<Self> {
+        (!value.is_infinite()).then_some(Value::Float(value))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int(left), Value::Int(right)) => left.partial_cmp(right),
+            (left, right) => left.as_f64().partial_cmp(&right.as_f64()),
+        }
+    }
+}
+
+// Floats always show a decimal point, so `2.0` isn't mistaken for the integer `2`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) if value.fract() == 0.0 => write!(f, "{:.1}", value),
+            Value::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// The default backend: integers with checked arithmetic, and floats once either operand is one.
+// Dividing two integers stays integer division.
+impl Numeric for Value {
+    fn from_i64(value: i64) -> Self {
+        Value::Int(value)
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        Some(Value::Float(value))
+    }
+
+    fn add(self, other: Self) -> Option<Self> {
+        self.promote(other, i64::checked_add, |left, right| left + right)
+    }
+
+    fn sub(self, other: Self) -> Option<Self> {
+        self.promote(other, i64::checked_sub, |left, right| left - right)
+    }
+
+    fn mul(self, other: Self) -> Option<Self> {
+        self.promote(other, i64::checked_mul, |left, right| left * right)
+    }
+
+    fn div(self, other: Self) -> Option<Self> {
+        self.promote(other, i64::checked_div, |left, right| left / right)
+    }
+
+    fn rem(self, other: Self) -> Option<Self> {
+        self.promote(other, i64::checked_rem, |left, right| left % right)
+    }
+
+    fn same_kind(self, other: Self) -> bool {
+        matches!((self, other), (Value::Int(_), Value::Int(_)) | (Value::Float(_), Value::Float(_)))
+    }
+
+    fn divides_exactly(self, other: Self) -> bool {
+        match (self, other) {
+            (Value::Int(left), Value::Int(right)) => left.divides_exactly(right),
+            _ => true,
+        }
+    }
+
+    fn pow(self, exponent: Self) -> Option<Self> {
+        self.promote(exponent, Numeric::pow, f64::powf)
+    }
+
+    fn neg(self) -> Option<Self> {
+        match self {
+            Value::Int(value) => value.checked_neg().map(Value::Int),
+            Value::Float(value) => Some(Value::Float(-value)),
+        }
+    }
+
+    fn is_integral(self) -> bool {
+        match self {
+            Value::Int(_) => true,
+            Value::Float(value) => value.fract() == 0.0,
+        }
+    }
+
+    fn type_name(self) -> &'static str {
+        Value::type_name(&self)
+    }
+
+    fn is_nan(self) -> bool {
+        matches!(self, Value::Float(value) if value.is_nan())
+    }
+
+    fn factorial(self) -> Option<Self> {
+        match self {
+            Value::Int(value) => Numeric::factorial(value).map(Value::Int),
+            Value::Float(value) => {
+                let product = (2..=value as i64)
+                    .try_fold(1.0, |product, n| Self::finite(product * n as f64).map(Value::as_f64))?;
+                Some(Value::Float(product))
+            }
+        }
+    }
+
+    fn abs(self) -> Option<Self> {
+        match self {
+            Value::Int(value) => value.checked_abs().map(Value::Int),
+            Value::Float(value) => Some(Value::Float(value.abs())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_name_names_each_variant() {
+        assert_eq!(Value::Int(3).type_name(), "int");
+        assert_eq!(Value::Float(3.0).type_name(), "float");
+        assert_eq!(Numeric::type_name(Value::Float(0.5)), "float");
+        assert_eq!(Numeric::type_name(7i64), "int");
+    }
+}
@@ -0,0 +1,125 @@
+use super::{ASTExpression, ASTExpressionKind, ASTStatement, ASTStatementKind, Ast};
+
+// A reference to a node of the AST, as produced by the walk functions.
+#[derive(Clone, Copy)]
+pub enum NodeRef<'a> {
+    Statement(&'a ASTStatement),
+    Expression(&'a ASTExpression),
+}
+
+// Collect every node of the AST, each parent before its children.
+pub fn walk_preorder(ast: &Ast) -> Vec<NodeRef<'_>> {
+    let mut nodes = Vec::new();
+    for statement in &ast.statements {
+        walk_statement(statement, &mut nodes, Order::Pre);
+    }
+    nodes
+}
+
+// Collect every node of the AST, each parent after its children.
+pub fn walk_postorder(ast: &Ast) -> Vec<NodeRef<'_>> {
+    let mut nodes = Vec::new();
+    for statement in &ast.statements {
+        walk_statement(statement, &mut nodes, Order::Post);
+    }
+    nodes
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Order {
+    Pre,
+    Post,
+}
+
+fn walk_statement<'a>(statement: &'a ASTStatement, nodes: &mut Vec<NodeRef<'a>>, order: Order) {
+    if order == Order::Pre {
+        nodes.push(NodeRef::Statement(statement));
+    }
+    match &statement.kind {
+        ASTStatementKind::Expression(expr) => walk_expression(expr, nodes, order),
+        ASTStatementKind::Let(statement) => walk_expression(&statement.initializer, nodes, order),
+        ASTStatementKind::Print(print) => {
+            for argument in &print.arguments {
+                walk_expression(argument, nodes, order);
+            }
+        }
+    }
+    if order == Order::Post {
+        nodes.push(NodeRef::Statement(statement));
+    }
+}
+
+// Walk the binary expressions down the left operands of a chain like `1 + 2 + 3` in a loop, so a
+// long chain can't exhaust the stack, then their right operands, innermost first.
+fn walk_expression<'a>(expression: &'a ASTExpression, nodes: &mut Vec<NodeRef<'a>>, order: Order) {
+    let mut chain = Vec::new();
+    let mut expression = expression;
+    loop {
+        if order == Order::Pre {
+            nodes.push(NodeRef::Expression(expression));
+        }
+        match &expression.kind {
+            ASTExpressionKind::Number(_) | ASTExpressionKind::Float(_) | ASTExpressionKind::Variable(_) => {}
+            ASTExpressionKind::Unary(expr) => walk_expression(&expr.operand, nodes, order),
+            ASTExpressionKind::Binary(expr) => {
+                chain.push((expression, &expr.right));
+                expression = &expr.left;
+                continue;
+            }
+            ASTExpressionKind::Parenthesized(expr) => walk_expression(&expr.expression, nodes, order),
+            ASTExpressionKind::Factorial(expr) => walk_expression(&expr.expression, nodes, order),
+            ASTExpressionKind::Absolute(expr) => walk_expression(&expr.expression, nodes, order),
+        }
+        break;
+    }
+    if order == Order::Post {
+        nodes.push(NodeRef::Expression(expression));
+    }
+    for (binary, right) in chain.into_iter().rev() {
+        walk_expression(right, nodes, order);
+        if order == Order::Post {
+            nodes.push(NodeRef::Expression(binary));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+
+    fn parse(input: &str) -> Ast {
+        let mut parser = Parser::new(Lexer::new(input).tokenize_all());
+        let mut ast = Ast::new();
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+        ast
+    }
+
+    // Label each node: `;` for a statement, the operator for a binary expression, the number for a literal.
+    fn labels(nodes: Vec<NodeRef<'_>>) -> Vec<String> {
+        nodes
+            .iter()
+            .map(|node| match node {
+                NodeRef::Statement(_) => ";".to_string(),
+                NodeRef::Expression(expression) => match expression.kind() {
+                    ASTExpressionKind::Binary(expr) => expr.operator.symbol().to_string(),
+                    ASTExpressionKind::Number(number) => number.number.to_string(),
+                    _ => "?".to_string(),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn preorder_visits_parents_first() {
+        assert_eq!(labels(walk_preorder(&parse("1 + 2 * 3"))), [";", "+", "1", "*", "2", "3"]);
+    }
+
+    #[test]
+    fn postorder_visits_children_first() {
+        assert_eq!(labels(walk_postorder(&parse("1 + 2 * 3"))), ["1", "2", "3", "*", "+", ";"]);
+    }
+}
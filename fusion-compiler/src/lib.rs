@@ -0,0 +1,681 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, Lines, Write};
+use std::rc::Rc;
+
+use crate::ast::{
+    evaluator::{ASTEvaluator, EvalError},
+    lexer::{Lexer, TextSpan, TokenKind},
+    parser::{ParseError, Parser, DEFAULT_MAX_DEPTH},
+    ASTBinaryOperatorKind, ASTVisitor, Ast,
+};
+
+pub mod ast;
+
+pub use crate::ast::diagnostics::{lint_tokens, Diagnostic};
+pub use crate::ast::value::Value;
+
+/// The default maximum size, in bytes, of the source accepted by `evaluate` (1 MiB).
+///
+/// Lexing, parsing and evaluation take time and memory in proportion to the length of the input,
+/// so this bounds both. How deeply an input may nest is limited separately, by `max_depth`.
+pub const DEFAULT_MAX_INPUT_LEN: usize = 1024 * 1024;
+
+/// Errors that can occur while evaluating a source string end to end.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FusionError {
+    /// The source is longer than the configured limit; both values are in bytes.
+    InputTooLarge { len: usize, limit: usize },
+    /// The source could not be parsed.
+    Parse(Vec<ParseError>),
+    /// Evaluating the parsed program failed.
+    Eval(EvalError),
+    /// Reading the source failed.
+    Io(String),
+    /// The program ran but its last statement produced no value, as with empty input or a `let`.
+    NoValue,
+}
+
+impl fmt::Display for FusionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FusionError::InputTooLarge { len, limit } => {
+                write!(f, "input is {} bytes, which exceeds the limit of {} bytes", len, limit)
+            }
+            FusionError::Parse(errors) => {
+                let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "{}", messages.join("; "))
+            }
+            FusionError::Eval(error) => write!(f, "{}", error),
+            FusionError::Io(message) => write!(f, "failed to read input: {}", message),
+            FusionError::NoValue => write!(f, "the program produced no value"),
+        }
+    }
+}
+
+impl std::error::Error for FusionError {}
+
+/// Options controlling how `evaluate_with_options` processes its input.
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    /// Inputs longer than this many bytes are rejected before lexing begins, without looking at
+    /// their contents.
+    pub max_input_len: usize,
+    /// When set, only these binary operators are accepted; any other is a parse error.
+    pub allowed_operators: Option<Vec<ASTBinaryOperatorKind>>,
+    /// Whether a postfix `!` is parsed as factorial, e.g. `5!`.
+    pub postfix_factorial: bool,
+    /// Whether `|expr|` is parsed as the absolute value of `expr`.
+    pub pipe_is_abs: bool,
+    /// Whether a number directly followed by `(` or a variable multiplies it, e.g. `2(3 + 4)` or `3x`.
+    pub implicit_mul: bool,
+    /// How deeply expressions may nest before parsing fails, counting each group, sign and right
+    /// operand, so `(1 + 2) * 3` nests 3 deep. A chain like `1 + 2 + 3` nests 2 deep however long
+    /// it is, as its left operands are parsed and evaluated in a loop.
+    pub max_depth: usize,
+    /// Rejects behaviour that is otherwise tolerated: empty input, integer division that truncates
+    /// a remainder, and arithmetic mixing an integer with a float, such as `1 + 2.0`.
+    pub strict: bool,
+    /// Rejects expression statements whose value is discarded because another statement follows.
+    pub deny_unused: bool,
+    /// Applies `//!prec <operator> <precedence>` pragmas at the top of the input, overriding
+    /// operator precedence for that parse.
+    pub pragmas: bool,
+    /// Variables bound before evaluation starts, as if by `let` statements.
+    pub variables: Vec<(String, Value)>,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            max_input_len: DEFAULT_MAX_INPUT_LEN,
+            allowed_operators: None,
+            postfix_factorial: false,
+            pipe_is_abs: false,
+            implicit_mul: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            strict: false,
+            deny_unused: false,
+            pragmas: false,
+            variables: Vec::new(),
+        }
+    }
+}
+
+/// Lexes, parses and evaluates `input` with the default options, returning the value of the last statement.
+///
+/// A program with no statements, such as empty or whitespace-only input, evaluates to `Ok(None)`.
+pub fn evaluate(input: &str) -> Result<Option<Value>, FusionError> {
+    evaluate_with_options(input, &EvalOptions::default())
+}
+
+/// Lexes, parses and evaluates `input` with the default options, returning the value of the last
+/// statement, or `FusionError::NoValue` if it has none.
+///
+/// ```
+/// use fusion_compiler::{compile_and_eval, Value};
+///
+/// assert_eq!(compile_and_eval("(7 + 8) * 8 / 2"), Ok(Value::Int(60)));
+/// assert!(compile_and_eval("(7 + ").is_err());
+/// ```
+pub fn compile_and_eval(input: &str) -> Result<Value, FusionError> {
+    evaluate(input)?.ok_or(FusionError::NoValue)
+}
+
+/// Lexes, parses and evaluates `input` with the given options, returning the value of the last statement.
+pub fn evaluate_with_options(input: &str, options: &EvalOptions) -> Result<Option<Value>, FusionError> {
+    evaluate_with_report(input, options).map(|report| report.value)
+}
+
+/// The outcome of an evaluation together with the work it took.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvalReport {
+    /// The value of the last statement, as returned by `evaluate`.
+    pub value: Option<Value>,
+    /// The number of AST nodes visited while evaluating.
+    pub steps: usize,
+}
+
+/// Like `evaluate_with_options`, but also reports how many steps the evaluation consumed.
+pub fn evaluate_with_report(input: &str, options: &EvalOptions) -> Result<EvalReport, FusionError> {
+    let mut ast = parse(input, options)?;
+
+    let mut eval = evaluator(options, Box::new(io::stdout()));
+    let value = run(&mut ast, &mut eval)?;
+    Ok(EvalReport { value, steps: eval.steps })
+}
+
+/// Creates an evaluator configured by `options`, with its variables already bound, that writes
+/// the output of `print` statements to `writer`.
+fn evaluator(options: &EvalOptions, writer: Box<dyn Write>) -> ASTEvaluator {
+    let mut eval = ASTEvaluator::with_writer(writer).with_strict(options.strict);
+    for (name, value) in &options.variables {
+        eval = eval.with_variable(name, *value);
+    }
+    eval
+}
+
+/// Runs `ast` on `eval`, which keeps the variables bound by earlier runs.
+fn run(ast: &mut Ast, eval: &mut ASTEvaluator) -> Result<Option<Value>, FusionError> {
+    eval.last_value = None;
+    ast.visit(eval);
+    match eval.error.take() {
+        Some(error) => Err(FusionError::Eval(error)),
+        None => Ok(eval.last_value),
+    }
+}
+
+/// Evaluates every statement of `input` and returns a JSON array with one result per statement.
+///
+/// A statement's result is `{"value": 3}`, `{"value": null}` for a statement without a value such
+/// as `let`, or `{"error": "..."}`. An error in one statement doesn't stop the ones after it.
+/// A statement that prints also has an `"output"` field holding what it printed, as in
+/// `{"value": null, "output": "3\n"}`; nothing is written to stdout.
+/// If the program doesn't parse, nothing is evaluated and the array holds one error per parse error.
+pub fn evaluate_program_json(input: &str) -> String {
+    let options = EvalOptions::default();
+    let results: Vec<String> = match parse(input, &options) {
+        Ok(ast) => {
+            let output = SharedBuffer::default();
+            let mut eval = evaluator(&options, Box::new(output.clone()));
+            ast.statements
+                .iter()
+                .map(|statement| {
+                    eval.last_value = None;
+                    eval.visit_statement(statement);
+                    let result = match (eval.error.take(), eval.last_value) {
+                        (Some(error), _) => format!("\"error\": {}", json_string(&error.to_string())),
+                        (None, Some(value)) => format!("\"value\": {}", value),
+                        (None, None) => "\"value\": null".to_string(),
+                    };
+                    match output.take() {
+                        printed if printed.is_empty() => format!("{{{}}}", result),
+                        printed => format!("{{{}, \"output\": {}}}", result, json_string(&printed)),
+                    }
+                })
+                .collect()
+        }
+        Err(FusionError::Parse(errors)) => errors.iter().map(|error| json_error(&error.to_string())).collect(),
+        Err(error) => vec![json_error(&error.to_string())],
+    };
+    format!("[{}]", results.join(", "))
+}
+
+/// Formats an error result object for `evaluate_program_json`.
+fn json_error(message: &str) -> String {
+    format!("{{\"error\": {}}}", json_string(message))
+}
+
+/// Quotes `text` as a JSON string, escaping the characters JSON requires.
+fn json_string(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    format!("\"{}\"", escaped)
+}
+
+/// A writer keeping what is written to it in memory, shared by its clones so the output can be
+/// read back after the writer is handed to an evaluator.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Removes and returns everything written so far.
+    fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow_mut().split_off(0)).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Lexes `input` and lays its tokens out in a table, one per line, with each token's kind, literal,
+/// byte range and `line:column` position. Whitespace tokens are left out.
+pub fn dump_tokens(input: &str) -> String {
+    let header = ["KIND", "LITERAL", "SPAN", "POSITION"].map(String::from);
+    let mut rows = vec![header];
+    for token in Lexer::new(input).filter(|token| token.kind != TokenKind::Whitespace) {
+        let literal = match token.kind {
+            TokenKind::EOF => String::new(),
+            _ => format!("{:?}", token.span.literal),
+        };
+        rows.push([
+            format!("{:?}", token.kind),
+            literal,
+            format!("{}..{}", token.span.start, token.span.end),
+            token.span.to_string(),
+        ]);
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(widths).map(|(cell, width)| format!("{:<width$}", cell)).collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Lexes and parses `input` without evaluating it, reporting any errors found.
+pub fn check(input: &str, options: &EvalOptions) -> Result<(), FusionError> {
+    parse(input, options).map(|_| ())
+}
+
+/// The statements parsed from a source string together with the problems found in it.
+pub struct ParseResult {
+    /// The statements that parsed. A statement with an error is left out, and parsing resumes
+    /// after the `;` that ends it.
+    pub ast: Ast,
+    /// Every parse error, in the order found. Empty when the source is valid.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Lexes and parses `input`, keeping the statements that parsed alongside the diagnostics.
+///
+/// Unlike `check`, a malformed program still yields the part of its AST that parsed.
+pub fn parse_all(input: &str, options: &EvalOptions) -> ParseResult {
+    if let Err(error) = check_len(input, options) {
+        return ParseResult {
+            ast: Ast::new(),
+            diagnostics: vec![Diagnostic {
+                message: error.to_string(),
+                span: TextSpan::new(0, 0, String::new()),
+            }],
+        };
+    }
+    let (ast, errors) = parse_partial(input, options);
+    let diagnostics = errors.into_iter().map(|error| Diagnostic { message: error.message, span: error.span }).collect();
+    ParseResult { ast, diagnostics }
+}
+
+/// Lexes and parses `input` into an `Ast`, enforcing the input length limit first.
+fn parse(input: &str, options: &EvalOptions) -> Result<Ast, FusionError> {
+    check_len(input, options)?;
+    let (ast, errors) = parse_partial(input, options);
+    if !errors.is_empty() {
+        return Err(FusionError::Parse(errors));
+    }
+    Ok(ast)
+}
+
+/// Rejects `input` if it is longer than the configured limit.
+fn check_len(input: &str, options: &EvalOptions) -> Result<(), FusionError> {
+    if input.len() > options.max_input_len {
+        return Err(FusionError::InputTooLarge { len: input.len(), limit: options.max_input_len });
+    }
+    Ok(())
+}
+
+/// Parses `input` up to its first unrecoverable error, returning the statements and every error.
+fn parse_partial(input: &str, options: &EvalOptions) -> (Ast, Vec<ParseError>) {
+    let tokens = Lexer::new(input).tokenize_all();
+
+    let mut ast = Ast::new();
+    let mut parser = Parser::new(tokens)
+        .with_postfix_factorial(options.postfix_factorial)
+        .with_pipe_is_abs(options.pipe_is_abs)
+        .with_implicit_mul(options.implicit_mul)
+        .with_max_depth(options.max_depth)
+        .with_strict(options.strict)
+        .with_deny_unused(options.deny_unused)
+        .with_pragmas(options.pragmas);
+    if let Some(operators) = &options.allowed_operators {
+        parser = parser.with_allowed_operators(operators.clone());
+    }
+    while let Some(stmt) = parser.next_statement() {
+        ast.add_statement(stmt);
+    }
+    (ast, parser.errors().to_vec())
+}
+
+/// Evaluates one source string after another, keeping the variables bound by earlier ones.
+pub struct Session {
+    options: EvalOptions,
+    evaluator: ASTEvaluator,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new(EvalOptions::default())
+    }
+}
+
+impl Session {
+    /// Creates a session that evaluates every source string with `options`.
+    pub fn new(options: EvalOptions) -> Self {
+        Self::with_writer(options, Box::new(io::stdout()))
+    }
+
+    /// Creates a session like `new` that writes the output of `print` statements to `writer`
+    /// instead of stdout.
+    pub fn with_writer(options: EvalOptions, writer: Box<dyn Write>) -> Self {
+        Self { evaluator: evaluator(&options, writer), options }
+    }
+
+    /// Lexes, parses and evaluates `input`, returning the value of its last statement.
+    ///
+    /// Evaluation stops at the first error. Bindings made by the statements before it stay in place.
+    pub fn evaluate(&mut self, input: &str) -> Result<Option<Value>, FusionError> {
+        parse(input, &self.options).and_then(|mut ast| run(&mut ast, &mut self.evaluator))
+    }
+
+    /// Returns the variables bound so far and their values, sorted by name.
+    pub fn variables(&self) -> Vec<(&str, Value)> {
+        let mut variables: Vec<_> = self.evaluator.variables().collect();
+        variables.sort_by_key(|(name, _)| *name);
+        variables
+    }
+}
+
+/// Lazily evaluates newline-delimited expressions read from a reader, yielding one result per line.
+///
+/// Lines share one set of variables, so a line can use the bindings made by the lines before it.
+pub struct EvalStream<R> {
+    lines: Lines<R>,
+    session: Session,
+}
+
+impl<R: BufRead> EvalStream<R> {
+    /// Creates a stream over `reader` using the default options.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, EvalOptions::default())
+    }
+
+    /// Creates a stream over `reader` that evaluates every line with `options`.
+    pub fn with_options(reader: R, options: EvalOptions) -> Self {
+        Self { lines: reader.lines(), session: Session::new(options) }
+    }
+}
+
+impl<R: BufRead> Iterator for EvalStream<R> {
+    type Item = Result<Option<Value>, FusionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        Some(match line {
+            Ok(line) => self.session.evaluate(&line),
+            Err(error) => Err(FusionError::Io(error.to_string())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_or_whitespace_only_program_has_no_value() {
+        assert_eq!(evaluate("// nothing"), Ok(None));
+        assert_eq!(evaluate("/* nothing */\n// at all\n"), Ok(None));
+        assert_eq!(evaluate(" \t\n "), Ok(None));
+        assert_eq!(evaluate(""), Ok(None));
+        assert_eq!(parse_all("// nothing", &EvalOptions::default()).ast.statements.len(), 0);
+    }
+
+    #[test]
+    fn eval_stream_shares_bindings_between_lines() {
+        let mut stream = EvalStream::new(io::Cursor::new("let x = 2\nx + 1\n"));
+        assert_eq!(stream.next(), Some(Ok(None)));
+        assert_eq!(stream.next(), Some(Ok(Some(Value::Int(3)))));
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn operator_whitelist_rejects_other_operators() {
+        let options = EvalOptions {
+            allowed_operators: Some(vec![ASTBinaryOperatorKind::Plus, ASTBinaryOperatorKind::Minus]),
+            ..EvalOptions::default()
+        };
+        assert_eq!(evaluate_with_options("5 - 2 + 1", &options), Ok(Some(Value::Int(4))));
+        let error = check("2 * 3", &options).unwrap_err();
+        assert_eq!(error.to_string(), "operator `*` is not allowed at 1:3");
+    }
+
+    #[test]
+    fn postfix_factorial_binds_tightest() {
+        let options = EvalOptions { postfix_factorial: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("5!", &options), Ok(Some(Value::Int(120))));
+        assert_eq!(evaluate_with_options("0!", &options), Ok(Some(Value::Int(1))));
+        assert_eq!(evaluate_with_options("5! + 1 == 121", &options), Ok(Some(Value::Int(1))));
+        assert_eq!(evaluate_with_options("2 * 3!", &options), Ok(Some(Value::Int(12))));
+        assert!(matches!(evaluate_with_options("25!", &options), Err(FusionError::Eval(EvalError::Overflow { .. }))));
+        assert!(matches!(evaluate("5!"), Err(FusionError::Parse(_))));
+    }
+
+    #[test]
+    fn report_counts_every_visited_node() {
+        // One statement and five expressions: `+`, `1`, `*`, `2` and `3`
+        let report = evaluate_with_report("1 + 2 * 3", &EvalOptions::default()).unwrap();
+        assert_eq!(report.value, Some(Value::Int(7)));
+        assert_eq!(report.steps, 6);
+        // A group is a node of its own
+        assert_eq!(evaluate_with_report("(1 + 2) * 3", &EvalOptions::default()).unwrap().steps, 7);
+    }
+
+    #[test]
+    fn pipes_take_the_absolute_value_when_enabled() {
+        let options = EvalOptions { pipe_is_abs: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("|3 - 5|", &options), Ok(Some(Value::Int(2))));
+        assert_eq!(evaluate_with_options("||-3| - 5|", &options), Ok(Some(Value::Int(2))));
+        assert_eq!(evaluate_with_options("let x = -4; ||x| - 1| * 2", &options), Ok(Some(Value::Int(6))));
+        assert!(matches!(evaluate("|3 - 5|"), Err(FusionError::Parse(_))));
+    }
+
+    #[test]
+    fn implicit_mul_multiplies_a_number_by_a_group() {
+        let options = EvalOptions { implicit_mul: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("2(3 + 4)", &options), Ok(Some(Value::Int(14))));
+        assert_eq!(evaluate_with_options("2(3)", &options), Ok(Some(Value::Int(6))));
+        assert_eq!(evaluate_with_options("1 + 2(3) ** 2", &options), Ok(Some(Value::Int(19))));
+        assert!(matches!(evaluate("2(3 + 4)"), Err(FusionError::Parse(_))));
+    }
+
+    #[test]
+    fn implicit_mul_multiplies_a_number_by_a_variable() {
+        let options = EvalOptions { implicit_mul: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("let x = 5; 3x", &options), Ok(Some(Value::Int(15))));
+        assert_eq!(evaluate_with_options("let x = 5; 1 + 2x ** 2", &options), Ok(Some(Value::Int(51))));
+        assert_eq!(evaluate_with_options("let x = 5; 2.5x", &options), Ok(Some(Value::Float(12.5))));
+        // Only a number directly before the variable implies a multiplication
+        assert!(matches!(evaluate_with_options("let x = 5; 3 x", &options), Err(FusionError::Parse(_))));
+        assert!(matches!(evaluate("let x = 5; 3x"), Err(FusionError::Parse(_))));
+    }
+
+    #[test]
+    fn deny_unused_rejects_a_discarded_expression_only() {
+        let options = EvalOptions { deny_unused: true, ..EvalOptions::default() };
+        let error = check("1 + 2; 3", &options).unwrap_err();
+        assert!(error.to_string().contains("unused result"), "{}", error);
+        assert_eq!(check("let x = 1 + 2; print(x); x", &options), Ok(()));
+        assert_eq!(check("1 + 2; 3", &EvalOptions::default()), Ok(()));
+    }
+
+    #[test]
+    fn program_json_captures_an_error_and_continues() {
+        assert_eq!(evaluate_program_json("1 / 0; 2 + 3"), "[{\"error\": \"division by zero at 1:3\"}, {\"value\": 5}]");
+        assert_eq!(evaluate_program_json("let x = 1; x"), "[{\"value\": null}, {\"value\": 1}]");
+    }
+
+    #[test]
+    fn program_json_returns_print_output_with_the_statement_that_printed_it() {
+        let json = evaluate_program_json("print(1, 2); 3; print(4 / 0)");
+        assert_eq!(
+            json,
+            "[{\"value\": null, \"output\": \"1 2\\n\"}, {\"value\": 3}, {\"error\": \"division by zero at 1:25\"}]"
+        );
+    }
+
+    #[test]
+    fn dump_tokens_lists_each_token_with_its_span() {
+        let expected = [
+            "KIND       LITERAL  SPAN  POSITION",
+            "Number(1)  \"1\"      0..1  1:1",
+            "Plus       \"+\"      2..3  1:3",
+            "Number(2)  \"2\"      4..5  1:5",
+            "EOF                 5..5  1:6",
+        ];
+        assert_eq!(dump_tokens("1 + 2"), expected.map(|line| format!("{}\n", line)).concat());
+    }
+
+    #[test]
+    fn leading_byte_order_mark_is_ignored() {
+        assert_eq!(evaluate("\u{FEFF}1 + 2"), Ok(Some(Value::Int(3))));
+        let error = evaluate("\u{FEFF}1 +").unwrap_err();
+        assert_eq!(error.to_string(), "expected expression after `+` at 1:3");
+    }
+
+    #[test]
+    fn session_keeps_bindings_made_before_an_error() {
+        let mut session = Session::default();
+        assert!(session.evaluate("let a = 1; 1 / 0; let b = 2").is_err());
+        assert_eq!(session.evaluate("a"), Ok(Some(Value::Int(1))));
+        assert!(matches!(session.evaluate("b"), Err(FusionError::Eval(EvalError::UndefinedVariable { .. }))));
+    }
+
+    #[test]
+    fn session_lists_its_variables_by_name() {
+        let mut session = Session::default();
+        session.evaluate("let b = 2.5; let a = 1").unwrap();
+        assert_eq!(session.variables(), [("a", Value::Int(1)), ("b", Value::Float(2.5))]);
+    }
+
+    fn strict() -> EvalOptions {
+        EvalOptions { strict: true, ..EvalOptions::default() }
+    }
+
+    #[test]
+    fn strict_mode_rejects_empty_input() {
+        assert_eq!(evaluate(""), Ok(None));
+        assert!(matches!(evaluate_with_options("", &strict()), Err(FusionError::Parse(_))));
+    }
+
+    #[test]
+    fn strict_mode_rejects_truncating_division() {
+        assert_eq!(evaluate("7 / 2"), Ok(Some(Value::Int(3))));
+        let result = evaluate_with_options("7 / 2", &strict());
+        assert!(matches!(result, Err(FusionError::Eval(EvalError::InexactDivision { .. }))));
+        assert_eq!(evaluate_with_options("8 / 2", &strict()), Ok(Some(Value::Int(4))));
+    }
+
+    #[test]
+    fn strict_mode_rejects_mixing_integers_and_floats() {
+        assert_eq!(evaluate("1 + 2.0"), Ok(Some(Value::Float(3.0))));
+        let result = evaluate_with_options("1 + 2.0", &strict());
+        assert!(matches!(result, Err(FusionError::Eval(EvalError::MixedOperands { .. }))));
+        let error = evaluate_with_options("1.5 * 2", &strict()).unwrap_err();
+        assert_eq!(error.to_string(), "operation mixes float and int operands at 1:5");
+        assert_eq!(evaluate_with_options("1.0 + 2.0", &strict()), Ok(Some(Value::Float(3.0))));
+    }
+
+    #[test]
+    fn strict_mode_rejects_leftover_tokens_but_allows_later_statements() {
+        assert!(matches!(evaluate_with_options("1 2", &strict()), Err(FusionError::Parse(_))));
+        assert_eq!(evaluate_with_options("let x = 2; x * 3", &strict()), Ok(Some(Value::Int(6))));
+    }
+
+    #[test]
+    fn parse_all_resumes_after_a_statement_with_an_error() {
+        let result = parse_all("1; 2; (3 +; 4", &EvalOptions::default());
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].span.start(), 9);
+        assert_eq!(result.ast.statements.len(), 3);
+        assert_eq!(result.ast.to_string(), "1; 2; 4");
+    }
+
+    #[test]
+    fn max_depth_counts_every_kind_of_nesting() {
+        let options = |max_depth| EvalOptions { max_depth, postfix_factorial: true, ..EvalOptions::default() };
+        for (input, depth) in [("((1))", 3), ("- -1", 3), ("2 ** 2 ** 2", 3), ("(1 + 2) * 3", 3), ("3!!", 3)] {
+            assert!(evaluate_with_options(input, &options(depth)).is_ok(), "{} at {}", input, depth);
+            let result = evaluate_with_options(input, &options(depth - 1));
+            assert!(matches!(result, Err(FusionError::Parse(_))), "{} at {}", input, depth - 1);
+        }
+    }
+
+    #[test]
+    fn left_associative_chains_do_not_count_towards_max_depth() {
+        let options = |max_depth| EvalOptions { max_depth, ..EvalOptions::default() };
+        let sums = "1".to_string() + &" + 1".repeat(100);
+        assert_eq!(evaluate(&sums), Ok(Some(Value::Int(101))));
+        assert!(evaluate_with_options("1 + 1 + 1 + 1", &options(2)).is_ok());
+        let result = evaluate_with_options("1 + 1 + 1 + 1", &options(1));
+        assert!(matches!(result, Err(FusionError::Parse(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn default_max_depth_rejects_long_chains_without_overflowing_the_stack() {
+        // Run on a thread with the default stack size, as the inputs once overflowed it
+        let result = std::thread::spawn(|| {
+            let signs = "-".repeat(100_000) + "1";
+            let powers = "1".to_string() + &"**1".repeat(100_000);
+            [signs, powers].iter().map(|input| evaluate(input)).collect::<Vec<_>>()
+        })
+        .join()
+        .expect("the inputs are rejected without crashing");
+        for error in result {
+            assert!(matches!(error, Err(FusionError::Parse(_))), "{:?}", error.map(|_| ()));
+        }
+    }
+
+    #[test]
+    fn long_left_associative_chains_evaluate_without_overflowing_the_stack() {
+        // Run on a thread with the default stack size, so parsing, evaluating, walking, formatting,
+        // folding and dropping the chains must all go down their left operands in a loop
+        let result = std::thread::spawn(|| {
+            let sums = "1".to_string() + &"+1".repeat(400_000);
+            let mixed = "1".to_string() + &"+2*3-6".repeat(100_000);
+            [sums, mixed]
+                .iter()
+                .map(|input| {
+                    let ast = parse_all(input, &EvalOptions::default()).ast;
+                    let nodes = crate::ast::walk::walk_postorder(&ast).len();
+                    let formatted = ast.to_string().len();
+                    let folded = crate::ast::fold::fold_constants(&ast).to_string();
+                    (evaluate(input), nodes, formatted, folded)
+                })
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .expect("the chains are evaluated without crashing");
+        assert_eq!(result[0], (Ok(Some(Value::Int(400_001))), 800_002, 1 + 4 * 400_000, "400001".to_string()));
+        assert_eq!(result[1], (Ok(Some(Value::Int(1))), 600_002, 1 + 12 * 100_000, "1".to_string()));
+    }
+
+    #[test]
+    fn oversized_input_is_rejected_before_lexing() {
+        // Each `@` is a lexical error, so a size error rather than a parse error shows the input
+        // was never tokenized
+        assert!(matches!(evaluate("@"), Err(FusionError::Parse(_))));
+        let input = "@".repeat(DEFAULT_MAX_INPUT_LEN + 1);
+        let result = evaluate(&input);
+        assert_eq!(result, Err(FusionError::InputTooLarge { len: input.len(), limit: DEFAULT_MAX_INPUT_LEN }));
+    }
+
+    #[test]
+    fn session_writes_print_output_to_its_writer() {
+        let output = SharedBuffer::default();
+        let mut session = Session::with_writer(EvalOptions::default(), Box::new(output.clone()));
+        assert_eq!(session.evaluate("print(1, 2 + 3)"), Ok(None));
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "1 5\n");
+    }
+}
@@ -1,28 +1,247 @@
-use crate::ast::{Ast, parser::Parser, evaluator::ASTEvaluator};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::{env, fs, process};
 
+use fusion_compiler::{check, dump_tokens, evaluate_with_options, parse_all, EvalOptions, FusionError, Session, Value};
 
-mod ast;
+// The environment variable holding the previous result, bound to `ans` by `--ans`.
+const ANS_VAR: &str = "FUSION_ANS";
 
+// The command-line options understood by the binary.
+#[derive(Default)]
+struct Cli {
+    // Source to lex and parse without evaluating, from `--check <source>`.
+    check: Option<String>,
+    // Source to evaluate with the previous result bound to `ans`, from `--ans <source>`.
+    ans: Option<String>,
+    // Source to run once, showing its tokens and AST, from `-e <source>`.
+    eval: Option<String>,
+    // Source to lex and print as a table of tokens, from `--tokens <source>`.
+    tokens: Option<String>,
+    // A source file to evaluate, given as the only positional argument.
+    path: Option<String>,
+    // Whether the result of `-e` or the file becomes the exit code, from `--exit-with-result`.
+    exit_with_result: bool,
+    options: EvalOptions,
+}
+
+// Parse the command-line arguments, returning a message describing the first invalid one.
+fn parse_args(args: &[String]) -> Result<Cli, String> {
+    let mut cli = Cli::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => {
+                let source = args.next().ok_or("--check expects a source string")?;
+                cli.check = Some(source.clone());
+            }
+            "--ans" => {
+                let source = args.next().ok_or("--ans expects a source string")?;
+                cli.ans = Some(source.clone());
+            }
+            "-e" => {
+                let source = args.next().ok_or("-e expects a source string")?;
+                cli.eval = Some(source.clone());
+            }
+            "--tokens" => {
+                let source = args.next().ok_or("--tokens expects a source string")?;
+                cli.tokens = Some(source.clone());
+            }
+            "--exit-with-result" => cli.exit_with_result = true,
+            "--strict" => cli.options.strict = true,
+            "--deny-unused" => cli.options.deny_unused = true,
+            "--pragmas" => cli.options.pragmas = true,
+            "--max-depth" => {
+                let value = args.next().ok_or("--max-depth expects a number")?;
+                cli.options.max_depth = value.parse().map_err(|_| format!("invalid --max-depth value `{}`", value))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown argument `{}`", arg)),
+            _ if cli.path.is_some() => return Err(format!("unexpected extra argument `{}`", arg)),
+            _ => cli.path = Some(arg.clone()),
+        }
+    }
+    Ok(cli)
+}
 
 fn main() {
-    let input: &str = "( 7  + 8) * 8 / 2";
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = match parse_args(&args) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            process::exit(2);
+        }
+    };
+    if let Some(source) = &cli.check {
+        process::exit(run_check(source, &cli.options));
+    }
+    if let Some(source) = &cli.ans {
+        process::exit(run_ans(source, cli.options));
+    }
+    if let Some(source) = &cli.tokens {
+        print!("{}", dump_tokens(source));
+        return;
+    }
+    if cli.exit_with_result {
+        let code = match (&cli.eval, &cli.path) {
+            (Some(source), _) => run_exit_with_result(source, &cli.options),
+            (None, Some(path)) => read_file(path).map_or(1, |source| run_exit_with_result(&source, &cli.options)),
+            (None, None) => {
+                eprintln!("Error: --exit-with-result expects -e <source> or a file");
+                2
+            }
+        };
+        process::exit(code);
+    }
+    if let Some(source) = &cli.eval {
+        process::exit(run_once(source, &cli.options));
+    }
+    if let Some(path) = &cli.path {
+        process::exit(run_file(path, &cli.options));
+    }
+    run_repl(cli.options);
+}
 
-    let mut lexer = ast::lexer::Lexer::new(input);
-    let mut tokens = Vec::new();
-    while let Some(token) = lexer.next_token(){
-        tokens.push(token);
+// Read expressions from stdin a line at a time until EOF or `quit`, printing each result.
+// Errors are printed and the loop carries on; `let` bindings last for the whole session, and
+// `:types` lists them with the type of each value.
+fn run_repl(options: EvalOptions) {
+    let interactive = io::stdin().is_terminal();
+    let mut session = Session::new(options);
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("> ");
+            io::stdout().flush().ok();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(error)) => {
+                eprintln!("Error: {}", error);
+                break;
+            }
+            None => break,
+        };
+        match line.trim() {
+            "quit" => break,
+            "" => continue,
+            ":types" => {
+                for (name, value) in session.variables() {
+                    println!("{}: {}", name, value.type_name());
+                }
+                continue;
+            }
+            _ => {}
+        }
+        match session.evaluate(&line) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(error) => eprintln!("Error: {}", error),
+        }
     }
+}
 
-    println!("{:?}", tokens);
+// Run the source through each stage in turn, printing the tokens, the AST and the result.
+// Returns the process exit code: 0 on success, 1 if the source fails to parse or evaluate.
+fn run_once(input: &str, options: &EvalOptions) -> i32 {
+    print!("{}", dump_tokens(input));
+    let mut parsed = parse_all(input, options);
+    if parsed.diagnostics.is_empty() {
+        parsed.ast.visualize();
+    }
+    print_result(evaluate_with_options(input, options))
+}
 
-    let mut ast = Ast::new();
-    let mut parser = Parser::new(tokens);
-    while let Some(stmt) = parser.next_statement() {
-        ast.add_statement(stmt);
+// Lex and parse the source without evaluating it, printing every diagnostic.
+// Returns the process exit code: 0 when the source is valid, 1 otherwise.
+fn run_check(source: &str, options: &EvalOptions) -> i32 {
+    match check(source, options) {
+        Ok(()) => 0,
+        Err(FusionError::Parse(errors)) => {
+            for error in errors {
+                eprintln!("Error: {}", error);
+            }
+            1
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            1
+        }
     }
+}
+
+// Read and evaluate a source file, printing the value of its last statement.
+// Returns the process exit code: 0 on success, 1 if the file can't be read or fails to evaluate.
+fn run_file(path: &str, options: &EvalOptions) -> i32 {
+    match read_file(path) {
+        Some(source) => print_result(evaluate_with_options(&source, options)),
+        None => 1,
+    }
+}
 
-    ast.visualize();
-    let mut eval = ASTEvaluator::new();
-    ast.visit(&mut eval);
-    println!("Result: {:?}", eval.last_value)
+// Read a source file, printing why it couldn't be read if it fails.
+fn read_file(path: &str) -> Option<String> {
+    fs::read_to_string(path).map_err(|error| eprintln!("Error: cannot read `{}`: {}", path, error)).ok()
+}
+
+// Evaluate the source and exit with its result, so a shell can use it directly:
+//
+//     fusion-compiler --exit-with-result -e "6 * 7"; echo $?    # prints 42
+//
+// Only integers from 0 to 255 can be exit codes; any other result, or an error, is reported on
+// stderr with exit code 1. Returns the process exit code.
+fn run_exit_with_result(source: &str, options: &EvalOptions) -> i32 {
+    match evaluate_with_options(source, options) {
+        Ok(Some(Value::Int(value @ 0..=255))) => value as i32,
+        Ok(Some(value)) => {
+            eprintln!("Error: result {} is not an integer from 0 to 255", value);
+            1
+        }
+        Ok(None) => {
+            eprintln!("Error: the program produced no value");
+            1
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            1
+        }
+    }
+}
+
+// Evaluate the source with `ans` bound to the result of the previous invocation, read from
+// FUSION_ANS, and print the new result alone so a shell can store it for the next one:
+//
+//     export FUSION_ANS=$(fusion-compiler --ans "ans * 2")
+//
+// An unset or empty FUSION_ANS leaves `ans` unbound, so using it is an error.
+// Returns the process exit code: 0 on success, 1 otherwise.
+fn run_ans(source: &str, mut options: EvalOptions) -> i32 {
+    if let Some(previous) = env::var(ANS_VAR).ok().filter(|value| !value.is_empty()) {
+        let previous = previous.trim();
+        let parsed = previous.parse().map(Value::Int).or_else(|_| previous.parse().map(Value::Float));
+        match parsed {
+            Ok(value) => options.variables.push(("ans".to_string(), value)),
+            Err(_) => {
+                eprintln!("Error: invalid {} value `{}`", ANS_VAR, previous);
+                return 1;
+            }
+        }
+    }
+    print_result(evaluate_with_options(source, &options))
+}
+
+// Print a result on its own line, or the error that replaced it.
+// Returns the process exit code: 0 on success, 1 otherwise.
+fn print_result(result: Result<Option<Value>, FusionError>) -> i32 {
+    match result {
+        Ok(value) => {
+            if let Some(value) = value {
+                println!("{}", value);
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            1
+        }
+    }
 }
@@ -0,0 +1,128 @@
+// Integration tests running the compiled binary, checking its output and exit code.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fusion-compiler")).args(args).output().expect("the binary runs")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn check_reports_parse_errors_without_evaluating() {
+    let output = run(&["--check", "1 + )"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("expected expression, found `)` at 1:5"), "{}", stderr(&output));
+
+    let output = run(&["--check", "1 / 0"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+// Run the binary with FUSION_ANS set, or removed when `ans` is `None`.
+fn run_with_ans(args: &[&str], ans: Option<&str>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_fusion-compiler"));
+    match ans {
+        Some(ans) => command.env("FUSION_ANS", ans),
+        None => command.env_remove("FUSION_ANS"),
+    };
+    command.args(args).output().expect("the binary runs")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn ans_chains_results_across_invocations() {
+    let first = run_with_ans(&["--ans", "2 + 3"], None);
+    assert!(first.status.success(), "{}", stderr(&first));
+    assert_eq!(stdout(&first), "5\n");
+
+    let second = run_with_ans(&["--ans", "ans * 2"], Some(&stdout(&first)));
+    assert!(second.status.success(), "{}", stderr(&second));
+    assert_eq!(stdout(&second), "10\n");
+}
+
+#[test]
+fn ans_before_any_result_is_an_error() {
+    let output = run_with_ans(&["--ans", "ans + 1"], None);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("ans"), "{}", stderr(&output));
+}
+
+#[test]
+fn source_file_argument_is_evaluated() {
+    let path = std::env::temp_dir().join(format!("fusion-cli-{}.fus", std::process::id()));
+    std::fs::write(&path, "2 * (3 + 4)").expect("the temp file is writable");
+    let output = run(&[path.to_str().unwrap()]);
+    std::fs::remove_file(&path).ok();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "14\n");
+}
+
+#[test]
+fn source_file_starting_with_a_byte_order_mark_is_evaluated() {
+    let path = std::env::temp_dir().join(format!("fusion-cli-bom-{}.fus", std::process::id()));
+    std::fs::write(&path, "\u{FEFF}1 + 2").expect("the temp file is writable");
+    let output = run(&[path.to_str().unwrap()]);
+    std::fs::remove_file(&path).ok();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "3\n");
+}
+
+#[test]
+fn missing_source_file_is_an_error() {
+    let output = run(&["does/not/exist.fus"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("cannot read `does/not/exist.fus`"), "{}", stderr(&output));
+}
+
+#[test]
+fn small_max_depth_rejects_deep_parentheses() {
+    let output = run(&["--max-depth", "2", "--check", "((((1))))"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("nested too deeply (limit is 2)"), "{}", stderr(&output));
+
+    assert!(run(&["--max-depth", "8", "--check", "((((1))))"]).status.success());
+}
+
+#[test]
+fn eval_flag_uses_the_command_line_options() {
+    assert!(!run(&["--max-depth", "2", "-e", "((((1))))"]).status.success());
+    assert!(!run(&["--strict", "-e", "1 2"]).status.success());
+}
+
+#[test]
+fn eval_flag_prints_the_result_with_display() {
+    let output = run(&["-e", "2 * 3"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with("\n6\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn exit_with_result_uses_the_value_as_exit_code() {
+    assert_eq!(run(&["--exit-with-result", "-e", "42"]).status.code(), Some(42));
+    assert_eq!(run(&["--exit-with-result", "-e", "256"]).status.code(), Some(1));
+}
+
+// Run the REPL, feeding it `input` on stdin.
+fn run_repl(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fusion-compiler"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+    child.stdin.take().expect("stdin is piped").write_all(input.as_bytes()).expect("the REPL reads its input");
+    child.wait_with_output().expect("the binary runs")
+}
+
+#[test]
+fn repl_types_lists_each_binding_with_its_type() {
+    let output = run_repl("let y = 2.5\nlet x = 1\n:types\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "x: int\ny: float\n");
+}
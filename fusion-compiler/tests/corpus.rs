@@ -0,0 +1,43 @@
+// Regression corpus: inputs that once crashed or hung the compiler, one per file in tests/corpus/.
+// Each must evaluate to Ok or Err without panicking, and within the time limit. Evaluation runs on
+// a thread with the default stack size, so an input that recurses too deeply aborts the test run.
+//
+// To add a case, save the offending input as a new file there; any bytes are accepted, and
+// invalid UTF-8 is replaced before evaluation.
+
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use fusion_compiler::evaluate;
+
+// How long a single input may take before it counts as a hang.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn corpus_inputs_evaluate_without_panicking_or_hanging() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("the corpus directory exists")
+        .map(|entry| entry.expect("corpus entries are readable").path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "the corpus in {} is empty", dir.display());
+
+    for path in paths {
+        let input = String::from_utf8_lossy(&fs::read(&path).expect("corpus files are readable")).into_owned();
+        // Evaluate on another thread so a hang can be detected rather than stalling the test run
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = evaluate(&input);
+            sender.send(()).ok();
+        });
+        match receiver.recv_timeout(TIMEOUT) {
+            Ok(()) => {}
+            Err(RecvTimeoutError::Timeout) => panic!("{} did not finish within {:?}", path.display(), TIMEOUT),
+            Err(RecvTimeoutError::Disconnected) => panic!("{} panicked", path.display()),
+        }
+    }
+}